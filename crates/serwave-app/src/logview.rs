@@ -0,0 +1,113 @@
+//! List model over the `LogStore`: rows are rendered only when the list view
+//! asks for them, i.e. for the lines on screen.
+
+use serwave_core::{LogStore, RenderOptions, TextEncoding};
+use slint::{Model, ModelNotify, ModelTracker, SharedString};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+pub struct LogModel {
+    store: Rc<RefCell<LogStore>>,
+    options: Cell<RenderOptions>,
+    /// What the view was last told about.
+    rows: Cell<usize>,
+    generation: Cell<u64>,
+    pushed: Cell<u64>,
+    evicted: Cell<u64>,
+    evicted_rows: Cell<u64>,
+    notify: ModelNotify,
+}
+
+impl LogModel {
+    pub fn new(store: Rc<RefCell<LogStore>>) -> Self {
+        Self {
+            store,
+            options: Cell::new(RenderOptions {
+                show_timestamp: true,
+                show_hex: false,
+                encoding: TextEncoding::Auto,
+            }),
+            rows: Cell::new(0),
+            generation: Cell::new(0),
+            pushed: Cell::new(0),
+            evicted: Cell::new(0),
+            evicted_rows: Cell::new(0),
+            notify: ModelNotify::default(),
+        }
+    }
+
+    /// Brings the view up to date with `store`; returns whether rows were added
+    /// at the bottom.
+    pub fn sync(&self, store: &LogStore, options: RenderOptions) -> bool {
+        let generation = store.generation();
+        if generation == self.generation.get() && options == self.options.get() {
+            return false;
+        }
+        let rows = store.visible_len();
+        let evicted = store.evicted().entries;
+        let evicted_rows = store.evicted_rows();
+        let pushed = store.len() as u64 + evicted;
+        let old_rows = self.rows.get();
+
+        // Nothing but pushes and evictions since last time (the steady state of
+        // a full log): rows only left at the top and arrived at the bottom
+        let changes = generation - self.generation.get();
+        let pushes = pushed.checked_sub(self.pushed.get());
+        let evictions = evicted.checked_sub(self.evicted.get());
+        let removed = evicted_rows.checked_sub(self.evicted_rows.get());
+        let incremental = match (pushes, evictions, removed) {
+            (Some(pushes), Some(evictions), Some(removed))
+                if options == self.options.get() && pushes + evictions == changes =>
+            {
+                // Rows pushed and evicted in between were never shown
+                let removed = (removed as usize).min(old_rows);
+                (old_rows - removed <= rows).then_some(removed)
+            }
+            _ => None,
+        };
+
+        self.options.set(options);
+        self.rows.set(rows);
+        self.generation.set(generation);
+        self.pushed.set(pushed);
+        self.evicted.set(evicted);
+        self.evicted_rows.set(evicted_rows);
+        match incremental {
+            Some(removed) => {
+                let kept = old_rows - removed;
+                if removed > 0 {
+                    self.notify.row_removed(0, removed);
+                }
+                if rows > kept {
+                    self.notify.row_added(kept, rows - kept);
+                }
+                rows > kept
+            }
+            None => {
+                self.notify.reset();
+                rows > old_rows
+            }
+        }
+    }
+}
+
+impl Model for LogModel {
+    type Data = SharedString;
+
+    fn row_count(&self) -> usize {
+        self.rows.get()
+    }
+
+    fn row_data(&self, row: usize) -> Option<SharedString> {
+        let store = self.store.borrow();
+        store.render_window(row..row + 1, &self.options.get()).pop().map(|line| line.text.into())
+    }
+
+    fn model_tracker(&self) -> &dyn ModelTracker {
+        &self.notify
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
@@ -1,20 +1,36 @@
 slint::include_modules!();
 
+mod logview;
+mod rules;
+mod scripts;
+mod sequences;
+
 use anyhow::Result;
-use serwave_core::{SerialConfig, SerialService, SerialEvent, LogStore, Direction, TextEncoding};
+use serwave_core::{SerialConfig, ReconnectPolicy, SerialService, SerialEvent, SerialError, LogStore, LogLimits, Direction, RenderOptions, TextEncoding};
+use serwave_core::{ShareServer, ShareConfig, ShareProtocol, ShareEvent, Permission};
+use serwave_core::{PortInfo, PortWatcher, PortEvent, PinSequence, ModemLine, Timestamp, TxPacing};
+use serwave_core::{ScriptHost, ScriptOutput, Framer, FramerConfig, FrameBoundary};
+use serwave_core::{DataBits, FlowControl, LineEnding, Parity, StopBits};
 use std::rc::Rc;
+use slint::Model;
 use std::cell::RefCell;
 use serde::{Serialize, Deserialize};
 use std::fs;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 use std::io::Write as IoWrite;
+use logview::LogModel;
+use rules::{ConnectRule, load_rules, save_rules, format_frame};
+use scripts::{ScriptRun, ScriptUpdate, describe_progress};
+use sequences::{SavedSequence, all_sequences, load_sequences, save_sequences};
 
 #[derive(Serialize, Deserialize, Clone)]
 struct SendPreset {
     name: String,
     data: String,
     is_hex: bool,
+    #[serde(default)]
+    group: String,
 }
 
 fn get_presets_path() -> PathBuf {
@@ -71,11 +87,7 @@ impl LogWriter {
         std::thread::spawn(move || {
             if let Ok(mut file_guard) = file_clone.lock() {
                 if let Some(file) = file_guard.as_mut() {
-                    let prefix = match direction {
-                        Direction::Rx => b"RX: ",
-                        Direction::Tx => b"TX: ",
-                    };
-                    let _ = file.write_all(prefix);
+                    let _ = file.write_all(direction.prefix().as_bytes());
                     let _ = file.write_all(&data);
                     let _ = file.write_all(b"\n");
                     let _ = file.flush();
@@ -88,16 +100,34 @@ impl LogWriter {
 fn main() -> Result<()> {
     let app = MainWindow::new()?;
 
-    let log_store = Rc::new(RefCell::new(LogStore::new(10000)));
+    let log_store = Rc::new(RefCell::new(LogStore::with_limits(LogLimits::default())));
     let serial_service: Rc<RefCell<Option<SerialService>>> = Rc::new(RefCell::new(None));
-    let rx_buffer: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(Vec::new()));
-    let last_rx_time: Rc<RefCell<Option<std::time::Instant>>> = Rc::new(RefCell::new(None));
+    let rx_framer: Rc<RefCell<Framer>> = Rc::new(RefCell::new(Framer::new(FramerConfig::default())));
     let presets: Rc<RefCell<Vec<SendPreset>>> = Rc::new(RefCell::new(load_presets()));
     let log_writer = Rc::new(LogWriter::new());
+    let share_server: Rc<RefCell<Option<ShareServer>>> = Rc::new(RefCell::new(None));
+    let port_watcher = PortWatcher::start(std::time::Duration::from_secs(1));
+    let rules: Rc<RefCell<Vec<ConnectRule>>> = Rc::new(RefCell::new(load_rules()));
+    let sequences: Rc<RefCell<Vec<SavedSequence>>> = Rc::new(RefCell::new(load_sequences()));
+    let script_run: Rc<RefCell<Option<ScriptRun>>> = Rc::new(RefCell::new(None));
+    let console_host: Rc<RefCell<Option<ScriptHost>>> = Rc::new(RefCell::new(None));
+    let console_text: Rc<RefCell<String>> = Rc::new(RefCell::new(String::new()));
+
+    app.set_log_lines(Rc::new(LogModel::new(log_store.clone())).into());
 
     // Initialize port list
     refresh_ports(&app);
     refresh_presets(&app, &presets.borrow());
+    refresh_rules(&app, &rules.borrow());
+    refresh_sequences(&app, &sequences.borrow());
+
+    // Ports present at startup are the watcher's baseline and never reported as added
+    for info in port_watcher.ports() {
+        if app.get_is_connected() {
+            break;
+        }
+        auto_connect(&app, &info, &rules.borrow(), &presets.borrow(), &serial_service, &log_store);
+    }
 
     // Connect button
     {
@@ -108,32 +138,77 @@ fn main() -> Result<()> {
         app.on_connect_clicked(move || {
             let app = app_weak.unwrap();
             let port_display = app.get_selected_port().to_string();
-            let baud_rate = app.get_baud_rate() as u32;
+            let custom_port = app.get_custom_port().trim().to_string();
 
-            if port_display.is_empty() {
+            if port_display.is_empty() && custom_port.is_empty() {
                 return;
             }
 
-            let port_name = port_display.split_whitespace().next().unwrap_or(&port_display).to_string();
-
-            let config = SerialConfig {
-                port_name: port_name.clone(),
-                baud_rate,
-                ..Default::default()
+            // A network endpoint typed by the user takes precedence over the port list
+            let port_name = if !custom_port.is_empty() {
+                custom_port
+            } else {
+                port_display.split_whitespace().next().unwrap_or(&port_display).to_string()
             };
 
-            match SerialService::open(config) {
-                Ok(service) => {
-                    *serial_service.borrow_mut() = Some(service);
-                    app.set_is_connected(true);
-                    log_store.borrow_mut().push(Direction::Tx, format!("已连接到 {}", port_name).into_bytes());
-                    update_log_display(&app, &log_store.borrow());
-                }
-                Err(e) => {
-                    log_store.borrow_mut().push(Direction::Tx, format!("连接失败: {}", e).into_bytes());
-                    update_log_display(&app, &log_store.borrow());
+            let config = config_from_ui(&app, port_name);
+            open_port(&app, &serial_service, &log_store, config);
+        });
+    }
+
+    // Add auto-connect rule from the selected port and current settings
+    {
+        let app_weak = app.as_weak();
+        let serial_service = serial_service.clone();
+        let log_store = log_store.clone();
+        let rules = rules.clone();
+        app.on_add_rule_clicked(move |name| {
+            let app = app_weak.unwrap();
+            let config = match serial_service.borrow().as_ref() {
+                Some(service) => service.config(),
+                None => {
+                    let port_name = app.get_selected_port().split_whitespace().next().unwrap_or_default().to_string();
+                    config_from_ui(&app, port_name)
                 }
-            }
+            };
+            let identity = SerialService::list_ports()
+                .into_iter()
+                .find(|p| p.port_name == config.port_name)
+                .and_then(|p| p.identity());
+            let Some(identity) = identity else {
+                log_store.borrow_mut().push(Direction::Tx, format!("{} 不是 USB 设备, 无法创建规则", config.port_name).into_bytes());
+                update_log_display(&app, &log_store.borrow());
+                return;
+            };
+            let rule = ConnectRule {
+                name: name.to_string(),
+                vid: identity.vid,
+                pid: identity.pid,
+                serial_number: identity.serial_number,
+                baud_rate: config.baud_rate,
+                frame: format_frame(config.data_bits, config.parity, config.stop_bits),
+                encoding: app.get_selected_encoding().to_string(),
+                preset_group: app.get_preset_group().to_string(),
+            };
+            let mut rules_mut = rules.borrow_mut();
+            rules_mut.retain(|r| r.name != rule.name);
+            rules_mut.push(rule);
+            save_rules(&rules_mut);
+            refresh_rules(&app, &rules_mut);
+        });
+    }
+
+    // Delete auto-connect rule
+    {
+        let app_weak = app.as_weak();
+        let rules = rules.clone();
+        app.on_delete_rule_clicked(move |label| {
+            let app = app_weak.unwrap();
+            let mut rules_mut = rules.borrow_mut();
+            rules_mut.retain(|r| r.label() != label.as_str());
+            save_rules(&rules_mut);
+            refresh_rules(&app, &rules_mut);
+            app.set_selected_rule("".into());
         });
     }
 
@@ -142,18 +217,31 @@ fn main() -> Result<()> {
         let app_weak = app.as_weak();
         let serial_service = serial_service.clone();
         let log_store = log_store.clone();
-        let rx_buffer = rx_buffer.clone();
-        let last_rx_time = last_rx_time.clone();
+        let rx_framer = rx_framer.clone();
+        let share_server = share_server.clone();
+        let script_run = script_run.clone();
+        let console_host = console_host.clone();
 
         app.on_disconnect_clicked(move || {
             let app = app_weak.unwrap();
+            if let Some(run) = script_run.borrow().as_ref() {
+                run.stop();
+            }
+            if let Some(host) = console_host.borrow().as_ref() {
+                host.stop();
+            }
+            if let Some(server) = share_server.borrow_mut().take() {
+                server.stop();
+                app.set_share_active(false);
+                refresh_share_clients(&app, None);
+            }
             if let Some(service) = serial_service.borrow().as_ref() {
                 service.close();
             }
             *serial_service.borrow_mut() = None;
-            rx_buffer.borrow_mut().clear();
-            *last_rx_time.borrow_mut() = None;
+            rx_framer.borrow_mut().clear();
             app.set_is_connected(false);
+            app.set_tx_queue_status("".into());
             log_store.borrow_mut().push(Direction::Tx, "已断开连接".as_bytes().to_vec());
             update_log_display(&app, &log_store.borrow());
         });
@@ -193,6 +281,16 @@ fn main() -> Result<()> {
         });
     }
 
+    // Cancel paced sends still queued
+    {
+        let serial_service = serial_service.clone();
+        app.on_cancel_tx_clicked(move || {
+            if let Some(service) = serial_service.borrow().as_ref() {
+                let _ = service.cancel_tx();
+            }
+        });
+    }
+
     // Clear button
     {
         let app_weak = app.as_weak();
@@ -214,6 +312,30 @@ fn main() -> Result<()> {
         });
     }
 
+    // Line settings changed: applied to an open port without reconnecting
+    {
+        let app_weak = app.as_weak();
+        let serial_service = serial_service.clone();
+        app.on_line_settings_changed(move || {
+            let app = app_weak.unwrap();
+            if let Some(service) = serial_service.borrow().as_ref() {
+                let _ = service.reconfigure(config_from_ui(&app, service.config().port_name));
+            }
+        });
+    }
+
+    // Send pacing changed: only the pacing, so the port is not reconfigured
+    {
+        let app_weak = app.as_weak();
+        let serial_service = serial_service.clone();
+        app.on_pacing_changed(move || {
+            let app = app_weak.unwrap();
+            if let Some(service) = serial_service.borrow().as_ref() {
+                let _ = service.set_pacing(pacing_from_ui(&app));
+            }
+        });
+    }
+
     // DTR toggle
     {
         let serial_service = serial_service.clone();
@@ -224,6 +346,190 @@ fn main() -> Result<()> {
         });
     }
 
+    // BREAK
+    {
+        let serial_service = serial_service.clone();
+        app.on_send_break_clicked(move || {
+            if let Some(service) = serial_service.borrow().as_ref() {
+                let _ = service.send_break(std::time::Duration::from_millis(250));
+            }
+        });
+    }
+    {
+        let serial_service = serial_service.clone();
+        app.on_break_toggled(move |state| {
+            if let Some(service) = serial_service.borrow().as_ref() {
+                let _ = service.set_break(state);
+            }
+        });
+    }
+
+    // Run pin sequence
+    {
+        let app_weak = app.as_weak();
+        let serial_service = serial_service.clone();
+        let sequences = sequences.clone();
+        app.on_run_sequence_clicked(move |name| {
+            let app = app_weak.unwrap();
+            let Some(sequence) = all_sequences(&sequences.borrow()).into_iter().find(|s| s.name == name.as_str()) else {
+                return;
+            };
+            if let Some(service) = serial_service.borrow().as_ref() {
+                let (dtr, rts) = sequence.final_states();
+                if service.run_pin_sequence(sequence).is_ok() {
+                    if let Some(dtr) = dtr {
+                        app.set_dtr_enabled(dtr);
+                    }
+                    if let Some(rts) = rts {
+                        app.set_rts_enabled(rts);
+                    }
+                }
+            }
+        });
+    }
+
+    // Save user-defined pin sequence
+    {
+        let app_weak = app.as_weak();
+        let sequences = sequences.clone();
+        let log_store = log_store.clone();
+        app.on_save_sequence_clicked(move |name, steps| {
+            let app = app_weak.unwrap();
+            if let Err(e) = PinSequence::parse(name.as_str(), &steps) {
+                log_store.borrow_mut().push(Direction::Info, format!("序列格式错误: {}", e).into_bytes());
+                update_log_display(&app, &log_store.borrow());
+                return;
+            }
+            let mut sequences_mut = sequences.borrow_mut();
+            sequences_mut.retain(|s| s.name != name.as_str());
+            sequences_mut.push(SavedSequence {
+                name: name.to_string(),
+                steps: steps.to_string(),
+            });
+            save_sequences(&sequences_mut);
+            refresh_sequences(&app, &sequences_mut);
+            app.set_selected_sequence(name);
+        });
+    }
+
+    // Delete user-defined pin sequence; built-ins are not stored and stay
+    {
+        let app_weak = app.as_weak();
+        let sequences = sequences.clone();
+        app.on_delete_sequence_clicked(move |name| {
+            let app = app_weak.unwrap();
+            let mut sequences_mut = sequences.borrow_mut();
+            sequences_mut.retain(|s| s.name != name.as_str());
+            save_sequences(&sequences_mut);
+            refresh_sequences(&app, &sequences_mut);
+            app.set_selected_sequence("".into());
+        });
+    }
+
+    // Run expect script against the current connection
+    {
+        let app_weak = app.as_weak();
+        let serial_service = serial_service.clone();
+        let log_store = log_store.clone();
+        let script_run = script_run.clone();
+        app.on_run_script_clicked(move |path| {
+            let app = app_weak.unwrap();
+            let Some(handle) = serial_service.borrow().as_ref().map(|s| s.handle()) else {
+                return;
+            };
+            match ScriptRun::start(path.trim(), handle) {
+                Ok(run) => {
+                    *script_run.borrow_mut() = Some(run);
+                    app.set_script_running(true);
+                    log_store.borrow_mut().push(Direction::Info, format!("开始执行脚本: {}", path).into_bytes());
+                }
+                Err(e) => {
+                    log_store.borrow_mut().push(Direction::Info, format!("脚本错误: {}", e).into_bytes());
+                }
+            }
+            update_log_display(&app, &log_store.borrow());
+        });
+    }
+    {
+        let script_run = script_run.clone();
+        app.on_stop_script_clicked(move || {
+            if let Some(run) = script_run.borrow().as_ref() {
+                run.stop();
+            }
+        });
+    }
+
+    // Log memory limits
+    {
+        let app_weak = app.as_weak();
+        let log_store = log_store.clone();
+        app.on_log_limits_changed(move || {
+            let app = app_weak.unwrap();
+            let defaults = LogLimits::default();
+            // Anything unparsable falls back to the default
+            let max_entries = app.get_log_max_entries().trim().parse::<usize>().ok().filter(|&n| n > 0).unwrap_or(defaults.max_entries);
+            let max_bytes = app.get_log_max_mb().trim().parse::<usize>().ok().filter(|&mb| mb > 0).map_or(defaults.max_bytes, |mb| mb * 1024 * 1024);
+            log_store.borrow_mut().set_limits(LogLimits { max_entries, max_bytes });
+            update_log_display(&app, &log_store.borrow());
+        });
+    }
+
+    // RX framing
+    {
+        let app_weak = app.as_weak();
+        let rx_framer = rx_framer.clone();
+        let log_store = log_store.clone();
+        app.on_framing_changed(move || {
+            let app = app_weak.unwrap();
+            match framer_from_ui(&app) {
+                Ok(config) => rx_framer.borrow_mut().set_config(config),
+                Err(e) => {
+                    log_store.borrow_mut().push(Direction::Info, format!("分帧设置错误: {}", e).into_bytes());
+                    update_log_display(&app, &log_store.borrow());
+                }
+            }
+        });
+    }
+
+    // Rhai script console
+    {
+        let app_weak = app.as_weak();
+        let serial_service = serial_service.clone();
+        let console_host = console_host.clone();
+        let console_text = console_text.clone();
+        app.on_console_run_clicked(move |source| {
+            let app = app_weak.unwrap();
+            let Some(handle) = serial_service.borrow().as_ref().map(|s| s.handle()) else {
+                return;
+            };
+            match ScriptHost::start(&source, handle) {
+                Ok(host) => {
+                    *console_host.borrow_mut() = Some(host);
+                    app.set_console_running(true);
+                }
+                Err(e) => append_console(&mut console_text.borrow_mut(), &format!("编译错误: {}", e)),
+            }
+            app.set_console_output(console_text.borrow().as_str().into());
+        });
+    }
+    {
+        let console_host = console_host.clone();
+        app.on_console_stop_clicked(move || {
+            if let Some(host) = console_host.borrow().as_ref() {
+                host.stop();
+            }
+        });
+    }
+    {
+        let app_weak = app.as_weak();
+        let console_text = console_text.clone();
+        app.on_console_clear_clicked(move || {
+            let app = app_weak.unwrap();
+            console_text.borrow_mut().clear();
+            app.set_console_output("".into());
+        });
+    }
+
     // RTS toggle
     {
         let serial_service = serial_service.clone();
@@ -234,12 +540,69 @@ fn main() -> Result<()> {
         });
     }
 
+    // Network sharing toggle
+    {
+        let app_weak = app.as_weak();
+        let serial_service = serial_service.clone();
+        let share_server = share_server.clone();
+        let log_store = log_store.clone();
+        app.on_share_toggled(move |enabled| {
+            let app = app_weak.unwrap();
+            if !enabled {
+                if let Some(server) = share_server.borrow_mut().take() {
+                    server.stop();
+                    log_store.borrow_mut().push(Direction::Tx, "已停止共享".as_bytes().to_vec());
+                }
+                refresh_share_clients(&app, None);
+                update_log_display(&app, &log_store.borrow());
+                return;
+            }
+
+            let service = serial_service.borrow();
+            let Some(service) = service.as_ref() else {
+                app.set_share_active(false);
+                return;
+            };
+            let config = ShareConfig {
+                bind_addr: app.get_share_addr().trim().to_string(),
+                protocol: if app.get_share_protocol() == "RFC2217" { ShareProtocol::Rfc2217 } else { ShareProtocol::Raw },
+                default_permission: if app.get_share_read_only() { Permission::ReadOnly } else { Permission::ReadWrite },
+            };
+            match ShareServer::start(service, config) {
+                Ok(server) => {
+                    log_store.borrow_mut().push(Direction::Tx, format!("已开启共享: {}", server.local_addr()).into_bytes());
+                    *share_server.borrow_mut() = Some(server);
+                }
+                Err(e) => {
+                    app.set_share_active(false);
+                    log_store.borrow_mut().push(Direction::Tx, format!("共享失败: {}", e).into_bytes());
+                }
+            }
+            update_log_display(&app, &log_store.borrow());
+        });
+    }
+
+    // Share client permission
+    {
+        let app_weak = app.as_weak();
+        let share_server = share_server.clone();
+        app.on_share_client_permission_changed(move |id, writable| {
+            let app = app_weak.unwrap();
+            if let Some(server) = share_server.borrow().as_ref() {
+                let permission = if writable { Permission::ReadWrite } else { Permission::ReadOnly };
+                server.set_permission(id as u64, permission);
+                refresh_share_clients(&app, Some(server));
+            }
+        });
+    }
+
     // Encoding changed
     {
         let app_weak = app.as_weak();
         let log_store = log_store.clone();
-        app.on_encoding_changed(move |_encoding| {
+        app.on_encoding_changed(move |encoding| {
             let app = app_weak.unwrap();
+            app.set_selected_encoding(encoding);
             update_log_display(&app, &log_store.borrow());
         });
     }
@@ -299,11 +662,13 @@ fn main() -> Result<()> {
             if let Some(existing) = presets_mut.iter_mut().find(|p| p.name == name.as_str()) {
                 existing.data = data.to_string();
                 existing.is_hex = is_hex;
+                existing.group = app.get_preset_group().to_string();
             } else {
                 presets_mut.push(SendPreset {
                     name: name.to_string(),
                     data: data.to_string(),
                     is_hex,
+                    group: app.get_preset_group().to_string(),
                 });
             }
             save_presets(&presets_mut);
@@ -311,6 +676,17 @@ fn main() -> Result<()> {
         });
     }
 
+    // Preset group changed
+    {
+        let app_weak = app.as_weak();
+        let presets = presets.clone();
+        app.on_preset_group_changed(move || {
+            let app = app_weak.unwrap();
+            refresh_presets(&app, &presets.borrow());
+            app.set_selected_preset("".into());
+        });
+    }
+
     // Delete preset
     {
         let app_weak = app.as_weak();
@@ -329,9 +705,14 @@ fn main() -> Result<()> {
     let app_weak = app.as_weak();
     let serial_service_clone = serial_service.clone();
     let log_store_clone = log_store.clone();
-    let rx_buffer_clone = rx_buffer.clone();
-    let last_rx_time_clone = last_rx_time.clone();
+    let rx_framer_clone = rx_framer.clone();
     let log_writer_clone = log_writer.clone();
+    let share_server_clone = share_server.clone();
+    let presets_clone = presets.clone();
+    let rules_clone = rules.clone();
+    let script_run_clone = script_run.clone();
+    let console_host_clone = console_host.clone();
+    let console_text_clone = console_text.clone();
 
     let _timer = slint::Timer::default();
     _timer.start(slint::TimerMode::Repeated, std::time::Duration::from_millis(50), move || {
@@ -339,40 +720,83 @@ fn main() -> Result<()> {
             if let Some(service) = serial_service_clone.borrow().as_ref() {
                 while let Ok(event) = service.events().try_recv() {
                     match event {
-                        SerialEvent::Rx(data) => {
-                            let now = std::time::Instant::now();
-                            let mut buf = rx_buffer_clone.borrow_mut();
-                            let mut last_time = last_rx_time_clone.borrow_mut();
-
-                            let force_flush = if let Some(last) = *last_time {
-                                now.duration_since(last).as_millis() > 100
-                            } else {
-                                false
-                            };
-
-                            buf.extend_from_slice(&data);
-
-                            while let Some(pos) = buf.iter().position(|&b| b == b'\n') {
-                                let line: Vec<u8> = buf.drain(..=pos).collect();
-                                log_store_clone.borrow_mut().push(Direction::Rx, line.clone());
-                                log_writer_clone.write_entry(Direction::Rx, &line);
+                        SerialEvent::Rx { data, at } => {
+                            for frame in rx_framer_clone.borrow_mut().push(&data, at) {
+                                log_writer_clone.write_entry(Direction::Rx, &frame.data);
+                                log_store_clone.borrow_mut().push_at(Direction::Rx, frame.data, frame.at);
                             }
-
-                            if (force_flush && !buf.is_empty()) || buf.len() > 1024 {
-                                let line = buf.drain(..).collect::<Vec<u8>>();
-                                log_store_clone.borrow_mut().push(Direction::Rx, line.clone());
-                                log_writer_clone.write_entry(Direction::Rx, &line);
-                            }
-
-                            *last_time = Some(now);
                             update_log_display(&app, &log_store_clone.borrow());
                         }
                         SerialEvent::Error(e) => {
-                            log_store_clone.borrow_mut().push(Direction::Tx, format!("错误: {}", e).into_bytes());
+                            log_store_clone.borrow_mut().push(Direction::Tx, describe_error(&e).into_bytes());
+                            update_log_display(&app, &log_store_clone.borrow());
+                        }
+                        SerialEvent::TxProgress { sent, total, queued } => {
+                            let status = if queued == 0 {
+                                String::new()
+                            } else {
+                                format!("发送中 {}/{}, 队列剩余 {} 字节", sent, total, queued)
+                            };
+                            app.set_tx_queue_status(status.into());
+                        }
+                        SerialEvent::TxCancelled { dropped } => {
+                            app.set_tx_queue_status("".into());
+                            log_store_clone.borrow_mut().push(Direction::Info, format!("已取消发送, 丢弃 {} 字节", dropped).into_bytes());
                             update_log_display(&app, &log_store_clone.borrow());
                         }
                         SerialEvent::Closed => {
                             app.set_is_connected(false);
+                            app.set_tx_queue_status("".into());
+                            app.set_break_held(false);
+                        }
+                        SerialEvent::Disconnected(e) => {
+                            log_store_clone.borrow_mut().push(Direction::Tx, format!("设备已断开: {}", e).into_bytes());
+                            update_log_display(&app, &log_store_clone.borrow());
+                        }
+                        SerialEvent::Reconnecting { attempt, delay } => {
+                            log_store_clone.borrow_mut().push(
+                                Direction::Tx,
+                                format!("第 {} 次重连, 等待 {} ms...", attempt, delay.as_millis()).into_bytes(),
+                            );
+                            update_log_display(&app, &log_store_clone.borrow());
+                        }
+                        SerialEvent::Reconfigured(config) => {
+                            let note = format!(
+                                "串口参数已切换: {} {} 流控:{}",
+                                config.baud_rate,
+                                format_frame(config.data_bits, config.parity, config.stop_bits),
+                                config.flow_control
+                            );
+                            log_store_clone.borrow_mut().push(Direction::Info, note.clone().into_bytes());
+                            log_writer_clone.write_entry(Direction::Info, note.as_bytes());
+                            update_log_display(&app, &log_store_clone.borrow());
+                        }
+                        SerialEvent::Break(state) => {
+                            let note = if state { "BREAK 开始" } else { "BREAK 结束" };
+                            log_store_clone.borrow_mut().push(Direction::Info, note.as_bytes().to_vec());
+                            log_writer_clone.write_entry(Direction::Info, note.as_bytes());
+                            update_log_display(&app, &log_store_clone.borrow());
+                        }
+                        SerialEvent::PinChanged { line, state, .. } => {
+                            match line {
+                                ModemLine::Cts => app.set_cts_status(state),
+                                ModemLine::Dsr => app.set_dsr_status(state),
+                                ModemLine::Dcd => app.set_dcd_status(state),
+                                ModemLine::Ri => app.set_ri_status(state),
+                            }
+                            let note = format!("{} {}", line, if state { "↑" } else { "↓" });
+                            log_store_clone.borrow_mut().push(Direction::Info, note.clone().into_bytes());
+                            log_writer_clone.write_entry(Direction::Info, note.as_bytes());
+                            update_log_display(&app, &log_store_clone.borrow());
+                        }
+                        SerialEvent::PinSequenceDone(name) => {
+                            log_store_clone.borrow_mut().push(Direction::Info, format!("已执行序列: {}", name).into_bytes());
+                            update_log_display(&app, &log_store_clone.borrow());
+                        }
+                        SerialEvent::Reconnected(port) => {
+                            let _ = service.request_pin_states();
+                            log_store_clone.borrow_mut().push(Direction::Tx, format!("已重新连接到 {}", port).into_bytes());
+                            update_log_display(&app, &log_store_clone.borrow());
                         }
                         SerialEvent::PinStates(states) => {
                             app.set_cts_status(states.cts);
@@ -383,7 +807,119 @@ fn main() -> Result<()> {
                         _ => {}
                     }
                 }
-                let _ = service.request_pin_states();
+                // A partial line is shown once the device has gone quiet
+                let idle_frame = rx_framer_clone.borrow_mut().flush_idle(Timestamp::now());
+                if let Some(frame) = idle_frame {
+                    log_writer_clone.write_entry(Direction::Rx, &frame.data);
+                    log_store_clone.borrow_mut().push_at(Direction::Rx, frame.data, frame.at);
+                    update_log_display(&app, &log_store_clone.borrow());
+                }
+            }
+
+            if let Some(server) = share_server_clone.borrow().as_ref() {
+                let mut clients_changed = false;
+                while let Ok(event) = server.events().try_recv() {
+                    match event {
+                        ShareEvent::Connected(client) => {
+                            log_store_clone.borrow_mut().push(Direction::Tx, format!("共享客户端已连接: {}", client.peer).into_bytes());
+                            clients_changed = true;
+                        }
+                        ShareEvent::Disconnected(_) => {
+                            log_store_clone.borrow_mut().push(Direction::Tx, "共享客户端已断开".as_bytes().to_vec());
+                            clients_changed = true;
+                        }
+                        ShareEvent::Tx { data, .. } => {
+                            log_store_clone.borrow_mut().push(Direction::Tx, data.clone());
+                            log_writer_clone.write_entry(Direction::Tx, &data);
+                        }
+                        ShareEvent::Denied { .. } => {}
+                    }
+                    update_log_display(&app, &log_store_clone.borrow());
+                }
+                if clients_changed {
+                    refresh_share_clients(&app, Some(server));
+                }
+            }
+
+            let mut script_finished = false;
+            if let Some(run) = script_run_clone.borrow().as_ref() {
+                while let Ok(update) = run.updates().try_recv() {
+                    let note = match update {
+                        ScriptUpdate::Progress(progress) => describe_progress(&progress),
+                        ScriptUpdate::Finished(Ok(())) => {
+                            script_finished = true;
+                            "脚本执行完成".to_string()
+                        }
+                        ScriptUpdate::Finished(Err(e)) => {
+                            script_finished = true;
+                            format!("脚本失败: {}", e)
+                        }
+                    };
+                    log_store_clone.borrow_mut().push(Direction::Info, note.into_bytes());
+                    update_log_display(&app, &log_store_clone.borrow());
+                }
+            }
+            if script_finished {
+                *script_run_clone.borrow_mut() = None;
+                app.set_script_running(false);
+            }
+
+            let mut console_finished = false;
+            if let Some(host) = console_host_clone.borrow().as_ref() {
+                let mut console_changed = false;
+                while let Ok(output) = host.output().try_recv() {
+                    match output {
+                        ScriptOutput::Print(text) => {
+                            append_console(&mut console_text_clone.borrow_mut(), &text);
+                            console_changed = true;
+                        }
+                        ScriptOutput::Log(text) => {
+                            log_store_clone.borrow_mut().push(Direction::Info, text.into_bytes());
+                            update_log_display(&app, &log_store_clone.borrow());
+                        }
+                        ScriptOutput::Finished(result) => {
+                            let note = match result {
+                                Ok(()) => "-- 脚本结束".to_string(),
+                                Err(e) => format!("-- 脚本错误: {}", e),
+                            };
+                            append_console(&mut console_text_clone.borrow_mut(), &note);
+                            console_changed = true;
+                            console_finished = true;
+                        }
+                    }
+                }
+                if console_changed {
+                    app.set_console_output(console_text_clone.borrow().as_str().into());
+                }
+            }
+            if console_finished {
+                *console_host_clone.borrow_mut() = None;
+                app.set_console_running(false);
+            }
+
+            let mut ports_changed = false;
+            while let Ok(event) = port_watcher.events().try_recv() {
+                ports_changed = true;
+                match event {
+                    PortEvent::Added(info) => {
+                        log_store_clone.borrow_mut().push(Direction::Tx, format!("检测到新串口: {}", info.port_name).into_bytes());
+                        // Newly attached devices are what the user most likely wants next
+                        if !app.get_is_connected() {
+                            app.set_selected_port(port_label(&info).into());
+                            auto_connect(&app, &info, &rules_clone.borrow(), &presets_clone.borrow(), &serial_service_clone, &log_store_clone);
+                        }
+                    }
+                    PortEvent::Removed(info) => {
+                        log_store_clone.borrow_mut().push(Direction::Tx, format!("串口已移除: {}", info.port_name).into_bytes());
+                        if !app.get_is_connected() && app.get_selected_port() == port_label(&info).as_str() {
+                            app.set_selected_port("".into());
+                        }
+                    }
+                }
+            }
+            if ports_changed {
+                refresh_ports(&app);
+                update_log_display(&app, &log_store_clone.borrow());
             }
         });
 
@@ -391,15 +927,23 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Adds a line to the script console, dropping the oldest output past the limit.
+fn append_console(text: &mut String, line: &str) {
+    const MAX_CONSOLE_LEN: usize = 64 * 1024;
+    text.push_str(line);
+    text.push('\n');
+    if text.len() > MAX_CONSOLE_LEN {
+        let mut cut = text.len() - MAX_CONSOLE_LEN;
+        while !text.is_char_boundary(cut) {
+            cut += 1;
+        }
+        text.drain(..cut);
+    }
+}
+
 fn refresh_ports(app: &MainWindow) {
     let ports = SerialService::list_ports();
-    let port_names: Vec<slint::SharedString> = ports.iter().map(|p| {
-        if let (Some(vid), Some(pid)) = (p.vid, p.pid) {
-            format!("{} ({:04X}:{:04X})", p.port_name, vid, pid).into()
-        } else {
-            p.port_name.clone().into()
-        }
-    }).collect();
+    let port_names: Vec<slint::SharedString> = ports.iter().map(|p| port_label(p).into()).collect();
 
     let port_list = Rc::new(slint::VecModel::from(port_names.clone()));
     app.set_port_list(port_list.into());
@@ -409,16 +953,199 @@ fn refresh_ports(app: &MainWindow) {
     }
 }
 
+fn port_label(p: &PortInfo) -> String {
+    if let (Some(vid), Some(pid)) = (p.vid, p.pid) {
+        format!("{} ({:04X}:{:04X})", p.port_name, vid, pid)
+    } else {
+        p.port_name.clone()
+    }
+}
+
+const BAUD_RATES: [u32; 8] = [9600, 19200, 38400, 57600, 115200, 230400, 460800, 921600];
+
+/// Line settings as currently chosen in the UI; a custom baud rate wins over the list.
+fn config_from_ui(app: &MainWindow, port_name: String) -> SerialConfig {
+    let baud_rate = app.get_custom_baud().trim().parse().ok().filter(|&b| b > 0).unwrap_or(app.get_baud_rate() as u32);
+    SerialConfig {
+        port_name,
+        baud_rate,
+        data_bits: app.get_data_bits().parse::<u8>().ok().and_then(|b| DataBits::try_from(b).ok()).unwrap_or(DataBits::Eight),
+        parity: match app.get_parity().as_str() {
+            "Odd" => Parity::Odd,
+            "Even" => Parity::Even,
+            _ => Parity::None,
+        },
+        stop_bits: if app.get_stop_bits() == "2" { StopBits::Two } else { StopBits::One },
+        flow_control: app.get_flow_control().parse().unwrap_or(FlowControl::None),
+        line_ending: match app.get_line_ending().as_str() {
+            "CR" => LineEnding::CR,
+            "CRLF" => LineEnding::CRLF,
+            _ => LineEnding::LF,
+        },
+        pacing: pacing_from_ui(app),
+        ..Default::default()
+    }
+}
+
+/// Paced sending, when either delay is set.
+fn pacing_from_ui(app: &MainWindow) -> Option<TxPacing> {
+    let millis = |text: slint::SharedString| std::time::Duration::from_millis(text.trim().parse().unwrap_or(0));
+    let pacing = TxPacing {
+        inter_byte: millis(app.get_inter_byte_delay()),
+        inter_line: millis(app.get_inter_line_delay()),
+    };
+    pacing.is_active().then_some(pacing)
+}
+
+/// RX framing rules as set in the UI; an error message for a malformed delimiter or length.
+fn framer_from_ui(app: &MainWindow) -> Result<FramerConfig, String> {
+    let param = app.get_frame_param().trim().to_string();
+    let boundary = match app.get_frame_mode().as_str() {
+        "CR" => FrameBoundary::Cr,
+        "CRLF" => FrameBoundary::CrLf,
+        "Auto" => FrameBoundary::Auto,
+        "分隔符" => {
+            let hex_str: String = param.chars().filter(|c| !c.is_whitespace()).collect();
+            match hex::decode(&hex_str) {
+                Ok(seq) if !seq.is_empty() => FrameBoundary::Sequence(seq),
+                _ => return Err(format!("分隔符应为HEX格式 (如: 0D0A), 当前为 \"{}\"", param)),
+            }
+        }
+        "固定长度" => match param.parse::<usize>() {
+            Ok(n) if n > 0 => FrameBoundary::Length(n),
+            _ => return Err(format!("无效的帧长度 \"{}\"", param)),
+        },
+        "仅超时" => FrameBoundary::None,
+        _ => FrameBoundary::Lf,
+    };
+    // Empty or zero turns the rule off
+    let idle_gap = app.get_frame_gap().trim().parse::<u64>().ok().filter(|&ms| ms > 0).map(std::time::Duration::from_millis);
+    let max_size = app.get_frame_max().trim().parse::<usize>().ok().filter(|&n| n > 0);
+    Ok(FramerConfig { boundary, idle_gap, max_size })
+}
+
+/// Reflects `config` in the settings controls, e.g. after an auto-connect rule chose it.
+fn show_config(app: &MainWindow, config: &SerialConfig) {
+    if BAUD_RATES.contains(&config.baud_rate) {
+        app.set_baud_rate(config.baud_rate as i32);
+        app.set_custom_baud("".into());
+    } else {
+        app.set_custom_baud(config.baud_rate.to_string().into());
+    }
+    app.set_data_bits(u8::from(config.data_bits).to_string().into());
+    app.set_parity(config.parity.to_string().into());
+    app.set_stop_bits(u8::from(config.stop_bits).to_string().into());
+    app.set_flow_control(config.flow_control.to_string().into());
+}
+
+/// Opens `config` as the active connection, taking the reconnect choice from the UI.
+fn open_port(app: &MainWindow, serial_service: &RefCell<Option<SerialService>>, log_store: &RefCell<LogStore>, config: SerialConfig) {
+    let port_name = config.port_name.clone();
+    let config = SerialConfig {
+        reconnect: app.get_auto_reconnect().then(ReconnectPolicy::default),
+        ..config
+    };
+
+    match SerialService::open(config) {
+        Ok(service) => {
+            // Baseline for the status line; later changes arrive as `PinChanged`
+            let _ = service.request_pin_states();
+            *serial_service.borrow_mut() = Some(service);
+            app.set_is_connected(true);
+            log_store.borrow_mut().push(Direction::Tx, format!("已连接到 {}", port_name).into_bytes());
+            update_log_display(app, &log_store.borrow());
+        }
+        Err(e) => {
+            log_store.borrow_mut().push(Direction::Tx, format!("连接失败: {}", e).into_bytes());
+            update_log_display(app, &log_store.borrow());
+        }
+    }
+}
+
+/// Opens `info` with the settings of the first auto-connect rule matching it, if any.
+fn auto_connect(
+    app: &MainWindow,
+    info: &PortInfo,
+    rules: &[ConnectRule],
+    presets: &[SendPreset],
+    serial_service: &RefCell<Option<SerialService>>,
+    log_store: &RefCell<LogStore>,
+) {
+    let Some(rule) = rules.iter().find(|r| r.matches(info)) else {
+        return;
+    };
+    log_store.borrow_mut().push(Direction::Tx, format!("匹配规则 \"{}\", 自动连接", rule.name).into_bytes());
+    app.set_selected_port(port_label(info).into());
+    let config = rule.apply(config_from_ui(app, info.port_name.clone()));
+    show_config(app, &config);
+    if !rule.encoding.is_empty() {
+        app.set_selected_encoding(rule.encoding.clone().into());
+    }
+    app.set_preset_group(rule.preset_group.clone().into());
+    refresh_presets(app, presets);
+    open_port(app, serial_service, log_store, config);
+}
+
+fn refresh_sequences(app: &MainWindow, sequences: &[SavedSequence]) {
+    let names: Vec<slint::SharedString> = all_sequences(sequences).into_iter().map(|s| s.name.into()).collect();
+    app.set_sequence_list(Rc::new(slint::VecModel::from(names)).into());
+}
+
+fn refresh_rules(app: &MainWindow, rules: &[ConnectRule]) {
+    let labels: Vec<slint::SharedString> = rules.iter().map(|r| r.label().into()).collect();
+    app.set_rule_list(Rc::new(slint::VecModel::from(labels)).into());
+}
+
 fn refresh_presets(app: &MainWindow, presets: &[SendPreset]) {
-    let preset_names: Vec<slint::SharedString> = presets.iter().map(|p| p.name.clone().into()).collect();
+    let group = app.get_preset_group();
+    let preset_names: Vec<slint::SharedString> = presets
+        .iter()
+        .filter(|p| group.is_empty() || p.group == group.as_str())
+        .map(|p| p.name.clone().into())
+        .collect();
     let preset_list = Rc::new(slint::VecModel::from(preset_names));
     app.set_preset_list(preset_list.into());
 }
 
+fn describe_error(err: &SerialError) -> String {
+    match err {
+        SerialError::PermissionDenied(port) if cfg!(target_os = "linux") && port.starts_with("/dev/tty") => {
+            format!("无权限访问 {}，请执行 sudo usermod -aG dialout $USER 后重新登录", port)
+        }
+        SerialError::PermissionDenied(port) => format!("无权限访问 {}", port),
+        SerialError::PortBusy(port) => format!("{} 已被其他程序占用", port),
+        SerialError::NotFound(port) => format!("找不到端口 {}", port),
+        SerialError::Disconnected(_) => "设备已断开".to_string(),
+        other => format!("错误: {}", other),
+    }
+}
+
+fn refresh_share_clients(app: &MainWindow, server: Option<&ShareServer>) {
+    let clients: Vec<ShareClient> = server.map(|s| s.clients()).unwrap_or_default().iter().map(|c| ShareClient {
+        id: c.id as i32,
+        peer: c.peer.to_string().into(),
+        writable: c.permission == Permission::ReadWrite,
+    }).collect();
+    app.set_share_clients(Rc::new(slint::VecModel::from(clients)).into());
+}
+
 fn update_log_display(app: &MainWindow, log_store: &LogStore) {
-    let show_timestamp = app.get_show_timestamp();
-    let show_hex = app.get_show_hex();
-    let encoding = app.get_selected_encoding().as_str().parse().unwrap_or(TextEncoding::Auto);
-    let text = log_store.to_text_with_encoding(show_timestamp, show_hex, encoding);
-    app.set_log_text(text.into());
+    let options = RenderOptions {
+        show_timestamp: app.get_show_timestamp(),
+        show_hex: app.get_show_hex(),
+        encoding: app.get_selected_encoding().as_str().parse().unwrap_or(TextEncoding::Auto),
+    };
+    let lines = app.get_log_lines();
+    if let Some(model) = lines.as_any().downcast_ref::<LogModel>() {
+        if model.sync(log_store, options) && app.get_follow_log() {
+            app.invoke_scroll_log_to_end();
+        }
+    }
+    let evicted = log_store.evicted();
+    let status = if evicted.entries == 0 {
+        String::new()
+    } else {
+        format!("已丢弃最早的 {} 条 ({} KB)", evicted.entries, evicted.bytes / 1024)
+    };
+    app.set_log_evicted_status(status.into());
 }
@@ -0,0 +1,118 @@
+//! Auto-connect rules: open a known USB device with its own settings as soon as it
+//! appears, or at startup when it is already attached.
+
+use serde::{Deserialize, Serialize};
+use serwave_core::{DataBits, Parity, PortIdentity, PortInfo, SerialConfig, StopBits};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ConnectRule {
+    pub name: String,
+    pub vid: u16,
+    pub pid: u16,
+    /// Matches any serial number when absent.
+    #[serde(default)]
+    pub serial_number: Option<String>,
+    pub baud_rate: u32,
+    /// Data bits, parity and stop bits in the usual short form, e.g. "8N1".
+    #[serde(default = "default_frame")]
+    pub frame: String,
+    #[serde(default)]
+    pub encoding: String,
+    #[serde(default)]
+    pub preset_group: String,
+}
+
+fn default_frame() -> String {
+    "8N1".to_string()
+}
+
+impl ConnectRule {
+    pub fn identity(&self) -> PortIdentity {
+        PortIdentity {
+            vid: self.vid,
+            pid: self.pid,
+            serial_number: self.serial_number.clone(),
+        }
+    }
+
+    pub fn matches(&self, info: &PortInfo) -> bool {
+        self.identity().matches(info)
+    }
+
+    /// `base` with this rule's line settings; everything the rule does not
+    /// cover, such as flow control and line ending, is kept.
+    pub fn apply(&self, base: SerialConfig) -> SerialConfig {
+        let (data_bits, parity, stop_bits) = parse_frame(&self.frame).unwrap_or((DataBits::Eight, Parity::None, StopBits::One));
+        SerialConfig {
+            baud_rate: self.baud_rate,
+            data_bits,
+            parity,
+            stop_bits,
+            ..base
+        }
+    }
+
+    /// One-line summary for the rule list.
+    pub fn label(&self) -> String {
+        let serial = self.serial_number.as_deref().map(|s| format!(" {}", s)).unwrap_or_default();
+        format!("{} [{:04X}:{:04X}{}] {} {}", self.name, self.vid, self.pid, serial, self.baud_rate, self.frame)
+    }
+}
+
+pub fn parse_frame(frame: &str) -> Option<(DataBits, Parity, StopBits)> {
+    let mut chars = frame.trim().chars();
+    let data_bits = match chars.next()? {
+        '5' => DataBits::Five,
+        '6' => DataBits::Six,
+        '7' => DataBits::Seven,
+        '8' => DataBits::Eight,
+        _ => return None,
+    };
+    let parity = match chars.next()?.to_ascii_uppercase() {
+        'N' => Parity::None,
+        'E' => Parity::Even,
+        'O' => Parity::Odd,
+        _ => return None,
+    };
+    let stop_bits = match chars.next()? {
+        '1' => StopBits::One,
+        '2' => StopBits::Two,
+        _ => return None,
+    };
+    chars.next().is_none().then_some((data_bits, parity, stop_bits))
+}
+
+pub fn format_frame(data_bits: DataBits, parity: Parity, stop_bits: StopBits) -> String {
+    let parity = match parity {
+        Parity::None => 'N',
+        Parity::Even => 'E',
+        Parity::Odd => 'O',
+    };
+    format!("{}{}{}", u8::from(data_bits), parity, u8::from(stop_bits))
+}
+
+fn get_rules_path() -> PathBuf {
+    let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push("serwave");
+    fs::create_dir_all(&path).ok();
+    path.push("rules.json");
+    path
+}
+
+pub fn load_rules() -> Vec<ConnectRule> {
+    let path = get_rules_path();
+    if let Ok(content) = fs::read_to_string(path) {
+        serde_json::from_str(&content).unwrap_or_default()
+    } else {
+        Vec::new()
+    }
+}
+
+pub fn save_rules(rules: &[ConnectRule]) {
+    let path = get_rules_path();
+    if let Ok(json) = serde_json::to_string_pretty(rules) {
+        let _ = fs::write(path, json);
+    }
+}
@@ -0,0 +1,51 @@
+//! Runs expect scripts from a file against the open connection, off the UI thread.
+
+use serwave_core::{Expect, ExpectError, Script, ScriptProgress, SerialHandle};
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+
+pub enum ScriptUpdate {
+    Progress(ScriptProgress),
+    Finished(Result<(), ExpectError>),
+}
+
+pub struct ScriptRun {
+    stop: Arc<AtomicBool>,
+    updates: Receiver<ScriptUpdate>,
+}
+
+impl ScriptRun {
+    /// Loads and parses the script at `path`, then runs it on a worker thread.
+    pub fn start(path: &str, handle: SerialHandle) -> Result<Self, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("无法读取 {}: {}", path, e))?;
+        let script = Script::parse(&source).map_err(|e| e.to_string())?;
+        let mut expect = Expect::new(handle);
+        let stop = expect.stop_flag();
+        let (tx, updates) = channel();
+        std::thread::spawn(move || {
+            let result = script.run(&mut expect, |p| {
+                let _ = tx.send(ScriptUpdate::Progress(p));
+            });
+            let _ = tx.send(ScriptUpdate::Finished(result));
+        });
+        Ok(Self { stop, updates })
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn updates(&self) -> &Receiver<ScriptUpdate> {
+        &self.updates
+    }
+}
+
+pub fn describe_progress(progress: &ScriptProgress) -> String {
+    match progress {
+        ScriptProgress::Step { line, text } => format!("脚本 L{}: {}", line, text),
+        ScriptProgress::Matched { text, .. } => format!("匹配: {}", text),
+        ScriptProgress::Message(message) => message.clone(),
+    }
+}
@@ -0,0 +1,44 @@
+//! User-defined DTR/RTS sequences, stored in their text form next to the presets.
+
+use serde::{Deserialize, Serialize};
+use serwave_core::PinSequence;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SavedSequence {
+    pub name: String,
+    /// Steps as accepted by `PinSequence::parse`, e.g. "DTR=0 RTS=1; 100ms; RTS=0".
+    pub steps: String,
+}
+
+/// Built-in sequences first, then every saved one that still parses.
+pub fn all_sequences(saved: &[SavedSequence]) -> Vec<PinSequence> {
+    let mut sequences = PinSequence::builtins();
+    sequences.extend(saved.iter().filter_map(|s| PinSequence::parse(s.name.clone(), &s.steps).ok()));
+    sequences
+}
+
+fn get_sequences_path() -> PathBuf {
+    let mut path = dirs::config_dir().unwrap_or_else(|| PathBuf::from("."));
+    path.push("serwave");
+    fs::create_dir_all(&path).ok();
+    path.push("sequences.json");
+    path
+}
+
+pub fn load_sequences() -> Vec<SavedSequence> {
+    let path = get_sequences_path();
+    if let Ok(content) = fs::read_to_string(path) {
+        serde_json::from_str(&content).unwrap_or_default()
+    } else {
+        Vec::new()
+    }
+}
+
+pub fn save_sequences(sequences: &[SavedSequence]) {
+    let path = get_sequences_path();
+    if let Ok(json) = serde_json::to_string_pretty(sequences) {
+        let _ = fs::write(path, json);
+    }
+}
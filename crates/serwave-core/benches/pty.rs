@@ -0,0 +1,100 @@
+//! TX latency and RX throughput of a `SerialService` driving the slave side
+//! of a PTY, with the master side playing the device.
+//!
+//! Run with `cargo bench -p serwave-core --bench pty`.
+
+#[cfg(target_os = "linux")]
+fn main() {
+    linux::run();
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("the PTY benchmark needs Linux");
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use serwave_core::{PtyPair, SerialEvent, SerialService};
+    use std::io::{Read, Write};
+    use std::time::{Duration, Instant};
+
+    const LATENCY_SAMPLES: usize = 2000;
+    const THROUGHPUT_BYTES: usize = 64 * 1024 * 1024;
+
+    pub fn run() {
+        let pair = PtyPair::open().expect("openpty");
+        let service = SerialService::open(pair.config()).expect("open service");
+        match service.events().recv_timeout(Duration::from_secs(2)) {
+            Ok(SerialEvent::Opened(_)) => {}
+            other => panic!("expected Opened, got {other:?}"),
+        }
+        let pair = tx_latency(pair, &service);
+        rx_throughput(pair, &service);
+    }
+
+    /// Time from `send` returning to the byte being readable on the master.
+    fn tx_latency(mut pair: PtyPair, service: &SerialService) -> PtyPair {
+        let mut samples = Vec::with_capacity(LATENCY_SAMPLES);
+        let mut byte = [0u8; 1];
+        for i in 0..LATENCY_SAMPLES {
+            let start = Instant::now();
+            service.send(vec![i as u8]).expect("send");
+            pair.master().read_exact(&mut byte).expect("read master");
+            samples.push(start.elapsed());
+            assert_eq!(byte[0], i as u8);
+        }
+        while service.events().try_recv().is_ok() {}
+
+        samples.sort();
+        let pct = |p: usize| samples[(samples.len() - 1) * p / 100];
+        println!(
+            "tx latency ({LATENCY_SAMPLES} sends): min {:?}  p50 {:?}  p99 {:?}  max {:?}",
+            samples[0],
+            pct(50),
+            pct(99),
+            samples[samples.len() - 1]
+        );
+        pair
+    }
+
+    /// Pushes a known pattern through the master as fast as the PTY takes it
+    /// and checks every byte arrives in order.
+    fn rx_throughput(mut pair: PtyPair, service: &SerialService) {
+        let pattern = |i: usize| (i % 251) as u8;
+        let writer = std::thread::spawn(move || {
+            let chunk: Vec<u8> = (0..4096).map(pattern).collect();
+            let start = Instant::now();
+            let mut sent = 0;
+            while sent < THROUGHPUT_BYTES {
+                // Continue the pattern where the previous write stopped
+                let offset = sent % 251;
+                let len = (THROUGHPUT_BYTES - sent).min(chunk.len() - offset);
+                pair.master().write_all(&chunk[offset..offset + len]).expect("write master");
+                sent += len;
+            }
+            (pair, start)
+        });
+
+        let mut received = 0;
+        while received < THROUGHPUT_BYTES {
+            match service.events().recv_timeout(Duration::from_secs(5)) {
+                Ok(SerialEvent::Rx { data, .. }) => {
+                    for (i, &b) in data.iter().enumerate() {
+                        assert_eq!(b, pattern(received + i), "byte {} corrupted or lost", received + i);
+                    }
+                    received += data.len();
+                }
+                Ok(_) => {}
+                Err(_) => panic!("stalled after {received} of {THROUGHPUT_BYTES} bytes"),
+            }
+        }
+        let (_pair, start) = writer.join().unwrap();
+        let elapsed = start.elapsed();
+        let mbit = THROUGHPUT_BYTES as f64 * 10.0 / elapsed.as_secs_f64() / 1e6;
+        println!(
+            "rx throughput: {} MiB in {elapsed:?}, {mbit:.1} Mbaud equivalent (8N1), no loss",
+            THROUGHPUT_BYTES >> 20
+        );
+    }
+}
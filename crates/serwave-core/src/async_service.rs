@@ -0,0 +1,216 @@
+//! Async front end for `SerialService`, for tokio-based code.
+//!
+//! The port is still driven by the usual worker threads; this only swaps the
+//! blocking channels for futures. Operations that the worker acknowledges
+//! (`send`, `set_dtr`, `set_rts`, `pin_states`) resolve once the worker has
+//! actually carried them out.
+
+use crate::error::SerialError;
+use crate::pinseq::PinSequence;
+use crate::serial_service::{Command, PinStates, Reply, SerialConfig, SerialEvent, SerialHandle, SerialService, Written};
+use crate::transaction::{interrupted, write_started, Collector, Transaction, TransactionError, TransactionResult};
+use crate::transport::Transport;
+use crate::txqueue::TxPacing;
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// How often a forwarding thread checks whether its stream was dropped.
+const FORWARD_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `SerialEvent`s as a `Stream`.
+pub struct EventStream {
+    rx: mpsc::UnboundedReceiver<SerialEvent>,
+}
+
+impl EventStream {
+    /// Moves events from a worker channel over to the async side on a
+    /// helper thread, which ends with either side.
+    fn forward(events: Receiver<SerialEvent>) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            while !tx.is_closed() {
+                match events.recv_timeout(FORWARD_POLL_INTERVAL) {
+                    Ok(event) => {
+                        if tx.send(event).is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+        Self { rx }
+    }
+
+    /// The next event, or `None` once the service has shut down.
+    pub async fn next(&mut self) -> Option<SerialEvent> {
+        self.rx.recv().await
+    }
+}
+
+impl futures_core::Stream for EventStream {
+    type Item = SerialEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<SerialEvent>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+pub struct AsyncSerialService {
+    service: SerialService,
+    events: Option<EventStream>,
+}
+
+impl AsyncSerialService {
+    /// Opens the port described by `cfg`; resolves once it is open.
+    pub async fn open(cfg: SerialConfig) -> Result<Self, SerialError> {
+        Self::start(SerialService::open(cfg)?).await
+    }
+
+    /// Like `open`, over an already connected transport.
+    pub async fn with_transport(cfg: SerialConfig, transport: Box<dyn Transport>) -> Result<Self, SerialError> {
+        Self::start(SerialService::with_transport(cfg, transport)?).await
+    }
+
+    /// Waits for the worker of a freshly created `service` to report the open.
+    async fn start(service: SerialService) -> Result<Self, SerialError> {
+        // Sole consumer of the service's own channel from here on
+        let mut events = EventStream::forward(service.events().clone());
+        loop {
+            match events.next().await {
+                Some(SerialEvent::Opened(_)) => break,
+                Some(SerialEvent::Error(e)) => return Err(e),
+                Some(_) => {}
+                None => return Err(SerialError::ChannelClosed),
+            }
+        }
+        Ok(Self { service, events: Some(events) })
+    }
+
+    /// Every event after `Opened` on the first call; later calls subscribe
+    /// afresh and only see events from then on.
+    pub fn events(&mut self) -> EventStream {
+        self.events
+            .take()
+            .unwrap_or_else(|| EventStream::forward(self.service.handle().subscribe()))
+    }
+
+    /// Writes `data` and resolves with the number of bytes written; paced
+    /// sends resolve once the last byte went out.
+    pub async fn send(&self, data: Vec<u8>) -> Result<usize, SerialError> {
+        self.request(|reply| Command::Send(data, Some(reply))).await.map(|written| written.len)
+    }
+
+    pub async fn set_dtr(&self, state: bool) -> Result<(), SerialError> {
+        self.request(|reply| Command::SetDtr(state, Some(reply))).await
+    }
+
+    pub async fn set_rts(&self, state: bool) -> Result<(), SerialError> {
+        self.request(|reply| Command::SetRts(state, Some(reply))).await
+    }
+
+    pub async fn pin_states(&self) -> Result<PinStates, SerialError> {
+        self.request(|reply| Command::GetPinStates(Some(reply))).await
+    }
+
+    /// Async counterpart of `SerialHandle::transact`.
+    pub async fn transact(&self, transaction: &Transaction) -> Result<TransactionResult, TransactionError> {
+        let deadline = tokio::time::Instant::now() + transaction.timeout;
+        let mut events = EventStream::forward(self.service.handle().subscribe());
+        let (tx, mut done) = oneshot::channel();
+        let reply: Reply<Written> = Box::new(move |result| {
+            let _ = tx.send(write_started(result));
+        });
+        self.service.handle().command(Command::Send(transaction.request.clone(), Some(reply)))?;
+
+        let mut collector = Collector::new(&transaction.matcher);
+        let exchange = async {
+            let mut written = None;
+            while let Some(event) = events.next().await {
+                if let Some(e) = interrupted(&event) {
+                    return Err(e.into());
+                }
+                match event {
+                    SerialEvent::Rx { data, at } => {
+                        if let Some((response, unsolicited)) = collector.push(&data) {
+                            let written = match written {
+                                Some(written) => written,
+                                // The reply can be queued behind the data that answered it
+                                None => (&mut done).await.unwrap_or(Err(SerialError::ChannelClosed))?,
+                            };
+                            let latency = at.since(&written);
+                            return Ok(TransactionResult { response, latency, unsolicited });
+                        }
+                    }
+                    // A failed write is reported both ways; take it from the reply
+                    SerialEvent::Error(_) => match done.try_recv() {
+                        Ok(Err(e)) => return Err(e.into()),
+                        Ok(Ok(at)) => written = Some(at),
+                        Err(_) => {}
+                    },
+                    _ => {}
+                }
+            }
+            Err(SerialError::ChannelClosed.into())
+        };
+        match tokio::time::timeout_at(deadline, exchange).await {
+            Ok(result) => result,
+            Err(_) => Err(TransactionError::Timeout {
+                timeout: transaction.timeout,
+                received: collector.into_received(),
+            }),
+        }
+    }
+
+    /// Drops paced sends that have not been written yet; their `send`
+    /// futures resolve with `SerialError::Cancelled`.
+    pub fn cancel_tx(&self) -> Result<(), SerialError> {
+        self.service.cancel_tx()
+    }
+
+    pub fn set_break(&self, state: bool) -> Result<(), SerialError> {
+        self.service.set_break(state)
+    }
+
+    pub fn send_break(&self, duration: Duration) -> Result<(), SerialError> {
+        self.service.send_break(duration)
+    }
+
+    pub fn run_pin_sequence(&self, sequence: PinSequence) -> Result<(), SerialError> {
+        self.service.run_pin_sequence(sequence)
+    }
+
+    pub fn reconfigure(&self, cfg: SerialConfig) -> Result<(), SerialError> {
+        self.service.reconfigure(cfg)
+    }
+
+    pub fn set_pacing(&self, pacing: Option<TxPacing>) -> Result<(), SerialError> {
+        self.service.set_pacing(pacing)
+    }
+
+    pub fn config(&self) -> SerialConfig {
+        self.service.config()
+    }
+
+    pub fn handle(&self) -> SerialHandle {
+        self.service.handle()
+    }
+
+    pub fn close(&self) {
+        self.service.close();
+    }
+
+    async fn request<T: Send + 'static>(&self, command: impl FnOnce(Reply<T>) -> Command) -> Result<T, SerialError> {
+        let (tx, rx) = oneshot::channel();
+        let reply: Reply<T> = Box::new(move |result| {
+            let _ = tx.send(result);
+        });
+        self.service.handle().command(command(reply))?;
+        // The reply is dropped unanswered when the port closes first
+        rx.await.unwrap_or(Err(SerialError::ChannelClosed))
+    }
+}
@@ -0,0 +1,114 @@
+use std::io;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Errors reported by `SerialService` and its transports.
+#[derive(Debug, Clone, Error)]
+pub enum SerialError {
+    #[error("port {0} is busy")]
+    PortBusy(String),
+    #[error("permission denied opening {0}")]
+    PermissionDenied(String),
+    #[error("port {0} not found")]
+    NotFound(String),
+    #[error("device disconnected: {0}")]
+    Disconnected(Arc<io::Error>),
+    #[error(transparent)]
+    Io(Arc<io::Error>),
+    #[error("serial service is closed")]
+    ChannelClosed,
+    #[error("send was cancelled")]
+    Cancelled,
+}
+
+impl SerialError {
+    /// Classifies an error raised while opening `port`.
+    pub fn open_failed(err: io::Error, port: &str) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => Self::NotFound(port.to_string()),
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied(port.to_string()),
+            _ if is_busy(&err) => Self::PortBusy(port.to_string()),
+            _ => Self::Io(Arc::new(err)),
+        }
+    }
+
+    /// Classifies an error raised by I/O on an open port.
+    pub fn from_io(err: io::Error) -> Self {
+        if is_disconnect(&err) {
+            Self::Disconnected(Arc::new(err))
+        } else {
+            Self::Io(Arc::new(err))
+        }
+    }
+
+    pub fn is_disconnect(&self) -> bool {
+        matches!(self, Self::Disconnected(_))
+    }
+}
+
+impl From<io::Error> for SerialError {
+    fn from(err: io::Error) -> Self {
+        Self::from_io(err)
+    }
+}
+
+impl From<serialport::Error> for SerialError {
+    fn from(err: serialport::Error) -> Self {
+        Self::Io(Arc::new(err.into()))
+    }
+}
+
+impl<T> From<crossbeam_channel::SendError<T>> for SerialError {
+    fn from(_: crossbeam_channel::SendError<T>) -> Self {
+        Self::ChannelClosed
+    }
+}
+
+/// Maps a `serialport` open failure, which folds "busy" into `NoDevice`.
+pub(crate) fn classify_serialport(err: serialport::Error, port: &str) -> SerialError {
+    match err.kind() {
+        serialport::ErrorKind::NoDevice => {
+            let description = err.description.to_lowercase();
+            // EBUSY on Unix, ERROR_ACCESS_DENIED on Windows
+            if description.contains("busy") || description.contains("access is denied") {
+                SerialError::PortBusy(port.to_string())
+            } else {
+                SerialError::NotFound(port.to_string())
+            }
+        }
+        serialport::ErrorKind::Io(io::ErrorKind::PermissionDenied) => SerialError::PermissionDenied(port.to_string()),
+        serialport::ErrorKind::Io(io::ErrorKind::NotFound) => SerialError::NotFound(port.to_string()),
+        _ => SerialError::Io(Arc::new(err.into())),
+    }
+}
+
+fn is_busy(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    if err.raw_os_error() == Some(libc::EBUSY) {
+        return true;
+    }
+    matches!(err.kind(), io::ErrorKind::AddrInUse)
+}
+
+fn is_disconnect(err: &io::Error) -> bool {
+    if matches!(
+        err.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::UnexpectedEof
+    ) {
+        return true;
+    }
+    // EIO, ENXIO and ENODEV are what a vanished USB serial adapter reports on Linux
+    #[cfg(unix)]
+    if matches!(err.raw_os_error(), Some(libc::EIO | libc::ENXIO | libc::ENODEV)) {
+        return true;
+    }
+    // ERROR_BAD_COMMAND, ERROR_GEN_FAILURE, ERROR_DEVICE_NOT_CONNECTED
+    #[cfg(windows)]
+    if matches!(err.raw_os_error(), Some(22 | 31 | 1167)) {
+        return true;
+    }
+    false
+}
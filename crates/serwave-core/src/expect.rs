@@ -0,0 +1,479 @@
+//! Expect-style automation of interactive consoles: wait for a prompt, answer it.
+//!
+//! [`Expect`] is the Rust API; [`Script`] runs the same steps from a small
+//! line-based language:
+//!
+//! ```text
+//! # Log into a Linux console and find its address
+//! timeout 10s
+//! set password "hunter2"
+//! wake:
+//! sendline ""
+//! expect
+//!     "login: $" => login
+//!     "# $" => shell
+//!     timeout => wake
+//! end
+//! login:
+//! sendline "root"
+//! expect "Password: $"
+//! sendline "${password}"
+//! shell:
+//! sendline "ip -4 addr show eth0"
+//! expect "inet (?P<ip>[0-9.]+)"
+//! log "board is at ${ip}"
+//! ```
+//!
+//! Patterns are regular expressions; their named groups become variables
+//! that later strings can use as `${name}`.
+
+use crate::error::SerialError;
+use crate::serial_service::{Command, SerialEvent, SerialHandle, Written};
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError};
+use regex::bytes::Regex;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// How often a wait checks the stop flag.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Unmatched output kept for matching; older bytes are dropped.
+const MAX_BUFFER: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Error)]
+pub enum ExpectError {
+    #[error("timed out after {timeout:?} waiting for {patterns:?}")]
+    Timeout { patterns: Vec<String>, timeout: Duration },
+    #[error("invalid pattern: {0}")]
+    Pattern(#[from] regex::Error),
+    #[error("unknown variable ${{{0}}}")]
+    UnknownVariable(String),
+    #[error("{0}")]
+    Failed(String),
+    #[error("stopped")]
+    Stopped,
+    #[error(transparent)]
+    Serial(#[from] SerialError),
+    #[error("line {line}: {source}")]
+    AtLine { line: usize, source: Box<ExpectError> },
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpectMatch {
+    /// Which of the patterns matched.
+    pub index: usize,
+    pub text: String,
+    /// Capture groups by number; group 0 is the whole match.
+    pub groups: Vec<Option<String>>,
+    /// Output skipped over before the match.
+    pub before: Vec<u8>,
+}
+
+/// Waits for patterns in the output of a port and answers them.
+pub struct Expect {
+    handle: SerialHandle,
+    events: Receiver<SerialEvent>,
+    buffer: Vec<u8>,
+    vars: HashMap<String, String>,
+    timeout: Duration,
+    stop: Arc<AtomicBool>,
+}
+
+impl Expect {
+    /// Starts watching the output of `handle`; only data arriving from now on is matched.
+    pub fn new(handle: SerialHandle) -> Self {
+        Self {
+            events: handle.subscribe(),
+            handle,
+            buffer: Vec::new(),
+            vars: HashMap::new(),
+            timeout: Duration::from_secs(10),
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Setting the flag from another thread aborts the current wait with `ExpectError::Stopped`.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(String::as_str)
+    }
+
+    pub fn set_var(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.vars.insert(name.into(), value.into());
+    }
+
+    pub fn vars(&self) -> &HashMap<String, String> {
+        &self.vars
+    }
+
+    /// Output received and not consumed by a match yet.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Writes `data` and waits until it went out.
+    pub fn send(&mut self, data: impl Into<Vec<u8>>) -> Result<(), ExpectError> {
+        let (tx, rx) = bounded(1);
+        let reply = Box::new(move |result: Result<Written, SerialError>| {
+            let _ = tx.send(result);
+        });
+        self.handle.command(Command::Send(data.into(), Some(reply)))?;
+        rx.recv().unwrap_or(Err(SerialError::ChannelClosed))?;
+        Ok(())
+    }
+
+    /// Sends `text` followed by the port's line ending.
+    pub fn sendline(&mut self, text: &str) -> Result<(), ExpectError> {
+        let mut data = text.as_bytes().to_vec();
+        data.extend_from_slice(self.handle.config().line_ending.as_bytes());
+        self.send(data)
+    }
+
+    pub fn expect(&mut self, pattern: &str) -> Result<ExpectMatch, ExpectError> {
+        self.expect_any(&[Regex::new(pattern)?])
+    }
+
+    /// Waits until one of `patterns` matches; the one matching earliest in
+    /// the output wins. Output up to the end of the match is consumed and
+    /// named groups are stored as variables.
+    pub fn expect_any(&mut self, patterns: &[Regex]) -> Result<ExpectMatch, ExpectError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(m) = self.take_match(patterns) {
+                return Ok(m);
+            }
+            if self.stop.load(Ordering::Relaxed) {
+                return Err(ExpectError::Stopped);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(ExpectError::Timeout {
+                    patterns: patterns.iter().map(|p| p.as_str().to_string()).collect(),
+                    timeout: self.timeout,
+                });
+            }
+            match self.events.recv_timeout((deadline - now).min(STOP_POLL_INTERVAL)) {
+                Ok(SerialEvent::Rx { data, .. }) => {
+                    self.buffer.extend_from_slice(&data);
+                    if self.buffer.len() > MAX_BUFFER {
+                        self.buffer.drain(..self.buffer.len() - MAX_BUFFER);
+                    }
+                }
+                // A reconnect may still bring the port back, so only a close ends the wait
+                Ok(SerialEvent::Closed) | Err(RecvTimeoutError::Disconnected) => {
+                    return Err(SerialError::ChannelClosed.into());
+                }
+                Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            }
+        }
+    }
+
+    /// Waits for `duration` without matching anything; output keeps being collected.
+    pub fn sleep(&mut self, duration: Duration) -> Result<(), ExpectError> {
+        let deadline = Instant::now() + duration;
+        while let Some(left) = deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) {
+            if self.stop.load(Ordering::Relaxed) {
+                return Err(ExpectError::Stopped);
+            }
+            std::thread::sleep(left.min(STOP_POLL_INTERVAL));
+        }
+        Ok(())
+    }
+
+    fn take_match(&mut self, patterns: &[Regex]) -> Option<ExpectMatch> {
+        let (index, caps) = patterns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, re)| re.captures(&self.buffer).map(|caps| (i, caps)))
+            .min_by_key(|(_, caps)| caps.get(0).map_or(usize::MAX, |m| m.start()))?;
+        let whole = caps.get(0)?;
+        let (start, end) = (whole.start(), whole.end());
+        let text = |m: regex::bytes::Match| String::from_utf8_lossy(m.as_bytes()).into_owned();
+        let groups = caps.iter().map(|g| g.map(text)).collect();
+        for name in patterns[index].capture_names().flatten() {
+            if let Some(value) = caps.name(name) {
+                self.vars.insert(name.to_string(), text(value));
+            }
+        }
+        let before = self.buffer[..start].to_vec();
+        let matched = String::from_utf8_lossy(&self.buffer[start..end]).into_owned();
+        self.buffer.drain(..end);
+        Some(ExpectMatch { index, text: matched, groups, before })
+    }
+
+    /// `template` with every `${name}` replaced by the variable's value.
+    pub fn expand(&self, template: &str) -> Result<String, ExpectError> {
+        let mut out = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find("${") {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + 2..];
+            let Some(len) = after.find('}') else {
+                out.push_str(&rest[start..]);
+                return Ok(out);
+            };
+            let name = &after[..len];
+            let value = self.var(name).ok_or_else(|| ExpectError::UnknownVariable(name.to_string()))?;
+            out.push_str(value);
+            rest = &after[len + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("line {line}: {message}")]
+pub struct ParseScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// What a running script is doing, for showing progress.
+#[derive(Debug, Clone)]
+pub enum ScriptProgress {
+    /// About to execute a line; `text` is its source.
+    Step { line: usize, text: String },
+    Matched { line: usize, text: String },
+    /// Output of a `log` command.
+    Message(String),
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    Timeout(Duration),
+    Send { text: String, newline: bool },
+    Expect { alternatives: Vec<(Regex, Option<String>)>, on_timeout: Option<String> },
+    Goto(String),
+    Sleep(Duration),
+    Set(String, String),
+    Log(String),
+    Fail(String),
+}
+
+#[derive(Debug, Clone)]
+struct ScriptLine {
+    number: usize,
+    text: String,
+    step: Step,
+}
+
+/// A parsed expect script; see the module docs for the language.
+#[derive(Debug, Clone)]
+pub struct Script {
+    lines: Vec<ScriptLine>,
+    /// Index into `lines` each label jumps to.
+    labels: HashMap<String, usize>,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Self, ParseScriptError> {
+        let mut lines = Vec::new();
+        let mut labels = HashMap::new();
+        let mut jumps = Vec::new();
+        let mut source_lines = source.lines().enumerate().map(|(i, l)| (i + 1, l.trim()));
+
+        while let Some((number, text)) = source_lines.next() {
+            let err = |message: String| ParseScriptError { line: number, message };
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            if let Some(label) = text.strip_suffix(':').filter(|l| is_identifier(l)) {
+                if labels.insert(label.to_string(), lines.len()).is_some() {
+                    return Err(err(format!("label {label} defined twice")));
+                }
+                continue;
+            }
+
+            let (command, arg) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+            let arg = arg.trim();
+            let step = match command {
+                "timeout" => Step::Timeout(parse_duration(arg).map_err(err)?),
+                "sleep" => Step::Sleep(parse_duration(arg).map_err(err)?),
+                "send" | "sendline" => Step::Send {
+                    text: parse_string(arg, true).map_err(err)?,
+                    newline: command == "sendline",
+                },
+                "log" => Step::Log(parse_string(arg, true).map_err(err)?),
+                "fail" => Step::Fail(parse_string(arg, true).map_err(err)?),
+                "goto" if is_identifier(arg) => {
+                    jumps.push((number, arg.to_string()));
+                    Step::Goto(arg.to_string())
+                }
+                "goto" => return Err(err(format!("invalid label \"{arg}\""))),
+                "set" => {
+                    let (name, value) = arg.split_once(char::is_whitespace).unwrap_or((arg, ""));
+                    if !is_identifier(name) {
+                        return Err(err(format!("invalid variable name \"{name}\"")));
+                    }
+                    Step::Set(name.to_string(), parse_string(value.trim(), true).map_err(err)?)
+                }
+                "expect" if !arg.is_empty() => {
+                    let pattern = parse_pattern(arg).map_err(err)?;
+                    Step::Expect { alternatives: vec![(pattern, None)], on_timeout: None }
+                }
+                "expect" => {
+                    let mut alternatives = Vec::new();
+                    let mut on_timeout = None;
+                    loop {
+                        let Some((number, text)) = source_lines.next() else {
+                            return Err(err("expect block without end".into()));
+                        };
+                        let err = |message: String| ParseScriptError { line: number, message };
+                        if text.is_empty() || text.starts_with('#') {
+                            continue;
+                        }
+                        if text == "end" {
+                            break;
+                        }
+                        let Some((pattern, label)) = text.rsplit_once("=>") else {
+                            return Err(err("expected \"pattern\" => label".into()));
+                        };
+                        let (pattern, label) = (pattern.trim(), label.trim());
+                        if !is_identifier(label) {
+                            return Err(err(format!("invalid label \"{label}\"")));
+                        }
+                        jumps.push((number, label.to_string()));
+                        if pattern == "timeout" {
+                            on_timeout = Some(label.to_string());
+                        } else {
+                            alternatives.push((parse_pattern(pattern).map_err(err)?, Some(label.to_string())));
+                        }
+                    }
+                    if alternatives.is_empty() {
+                        return Err(err("expect block without patterns".into()));
+                    }
+                    Step::Expect { alternatives, on_timeout }
+                }
+                _ => return Err(err(format!("unknown command \"{text}\""))),
+            };
+            lines.push(ScriptLine { number, text: text.to_string(), step });
+        }
+
+        if let Some((line, label)) = jumps.into_iter().find(|(_, label)| !labels.contains_key(label)) {
+            return Err(ParseScriptError { line, message: format!("unknown label {label}") });
+        }
+        Ok(Self { lines, labels })
+    }
+
+    /// Runs the script to its end, a `fail` or the first unhandled error,
+    /// reporting each step to `progress`.
+    pub fn run(&self, expect: &mut Expect, mut progress: impl FnMut(ScriptProgress)) -> Result<(), ExpectError> {
+        let mut pc = 0;
+        while let Some(line) = self.lines.get(pc) {
+            // Checked on every step, as loops of sends and jumps never wait
+            if expect.stop.load(Ordering::Relaxed) {
+                return Err(ExpectError::Stopped);
+            }
+            pc += 1;
+            progress(ScriptProgress::Step { line: line.number, text: line.text.clone() });
+            let at_line = |e: ExpectError| match e {
+                ExpectError::Stopped => e,
+                e => ExpectError::AtLine { line: line.number, source: Box::new(e) },
+            };
+            match &line.step {
+                Step::Timeout(timeout) => expect.set_timeout(*timeout),
+                Step::Sleep(duration) => expect.sleep(*duration).map_err(at_line)?,
+                Step::Send { text, newline } => {
+                    let text = expect.expand(text).map_err(at_line)?;
+                    let result = if *newline { expect.sendline(&text) } else { expect.send(text) };
+                    result.map_err(at_line)?;
+                }
+                Step::Expect { alternatives, on_timeout } => {
+                    let patterns: Vec<Regex> = alternatives.iter().map(|(re, _)| re.clone()).collect();
+                    match expect.expect_any(&patterns) {
+                        Ok(m) => {
+                            progress(ScriptProgress::Matched { line: line.number, text: m.text });
+                            if let Some(label) = &alternatives[m.index].1 {
+                                pc = self.labels[label];
+                            }
+                        }
+                        Err(e @ ExpectError::Timeout { .. }) => match on_timeout {
+                            Some(label) => pc = self.labels[label],
+                            None => return Err(at_line(e)),
+                        },
+                        Err(e) => return Err(at_line(e)),
+                    }
+                }
+                Step::Goto(label) => pc = self.labels[label],
+                Step::Set(name, value) => {
+                    let value = expect.expand(value).map_err(at_line)?;
+                    expect.set_var(name.clone(), value);
+                }
+                Step::Log(text) => progress(ScriptProgress::Message(expect.expand(text).map_err(at_line)?)),
+                Step::Fail(text) => return Err(at_line(ExpectError::Failed(expect.expand(text).map_err(at_line)?))),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `500ms`, `10s`, `1.5s` or plain seconds.
+fn parse_duration(arg: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration \"{arg}\"");
+    if let Some(ms) = arg.strip_suffix("ms") {
+        return ms.trim().parse().map(Duration::from_millis).map_err(|_| invalid());
+    }
+    let secs: f64 = arg.strip_suffix('s').unwrap_or(arg).trim().parse().map_err(|_| invalid())?;
+    Duration::try_from_secs_f64(secs).map_err(|_| invalid())
+}
+
+/// A double-quoted string. With `escapes`, `\n`, `\r`, `\t`, `\\`, `\"` and
+/// ASCII `\xNN` are decoded; otherwise only `\"` is, leaving the rest to the regex.
+fn parse_string(arg: &str, escapes: bool) -> Result<String, String> {
+    let inner = arg
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted string, got {arg}"))?;
+    let mut out = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => out.push('"'),
+            Some('n') if escapes => out.push('\n'),
+            Some('r') if escapes => out.push('\r'),
+            Some('t') if escapes => out.push('\t'),
+            Some('\\') if escapes => out.push('\\'),
+            Some('x') if escapes => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(u8::is_ascii)
+                    .ok_or_else(|| format!("invalid escape \\x{hex}"))?;
+                out.push(char::from(byte));
+            }
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    Ok(out)
+}
+
+fn parse_pattern(arg: &str) -> Result<Regex, String> {
+    let pattern = parse_string(arg, false)?;
+    Regex::new(&pattern).map_err(|e| e.to_string())
+}
@@ -0,0 +1,203 @@
+//! Splits the RX byte stream into frames (lines, records, packets) for display
+//! and logging.
+//!
+//! A frame ends at a boundary in the data, after an idle gap on the wire, or
+//! once it reaches the size limit, whichever comes first. Terminators stay in
+//! the frame they end.
+
+use crate::timestamp::Timestamp;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameBoundary {
+    /// No boundary in the data; only the idle gap and size limit apply.
+    None,
+    Lf,
+    Cr,
+    CrLf,
+    /// Any of CR, LF or CRLF, the pair counting as one terminator.
+    Auto,
+    /// An arbitrary terminator, e.g. `[0x7E]` or `b"\r\n>"`.
+    Sequence(Vec<u8>),
+    /// Frames of exactly this many bytes.
+    Length(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FramerConfig {
+    pub boundary: FrameBoundary,
+    /// A pause longer than this ends the pending frame.
+    pub idle_gap: Option<Duration>,
+    /// Pending data is cut into frames of at most this many bytes.
+    pub max_size: Option<usize>,
+}
+
+impl Default for FramerConfig {
+    fn default() -> Self {
+        Self {
+            boundary: FrameBoundary::Lf,
+            idle_gap: Some(Duration::from_millis(100)),
+            max_size: Some(1024),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub data: Vec<u8>,
+    /// Arrival of the chunk holding the first byte.
+    pub at: Timestamp,
+}
+
+pub struct Framer {
+    config: FramerConfig,
+    pending: Vec<u8>,
+    /// Arrival of the first pending byte and of the last chunk.
+    start: Option<Timestamp>,
+    last: Option<Timestamp>,
+    /// Pending bytes before this offset hold no boundary.
+    searched: usize,
+}
+
+impl Framer {
+    pub fn new(config: FramerConfig) -> Self {
+        Self {
+            config,
+            pending: Vec::new(),
+            start: None,
+            last: None,
+            searched: 0,
+        }
+    }
+
+    pub fn config(&self) -> &FramerConfig {
+        &self.config
+    }
+
+    /// Switches to new rules; pending data is kept and framed by them from now on.
+    pub fn set_config(&mut self, config: FramerConfig) {
+        self.config = config;
+        self.searched = 0;
+    }
+
+    pub fn pending(&self) -> &[u8] {
+        &self.pending
+    }
+
+    /// Adds a chunk that arrived at `at` and returns the frames it completed.
+    pub fn push(&mut self, data: &[u8], at: Timestamp) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        // Measured between arrivals, so it does not depend on how often the caller polls
+        if let Some(frame) = self.flush_idle(at) {
+            frames.push(frame);
+        }
+        if self.pending.is_empty() {
+            self.start = Some(at);
+        }
+        self.pending.extend_from_slice(data);
+        self.last = Some(at);
+
+        let max = self.config.max_size.filter(|&max| max > 0);
+        loop {
+            let end = match (self.find_end(), max) {
+                (Some(end), Some(max)) => end.min(max),
+                (None, Some(max)) if self.pending.len() >= max => max,
+                (Some(end), _) => end,
+                (None, _) => break,
+            };
+            frames.push(self.take(end));
+            // Whatever is left arrived with this chunk
+            self.start = Some(at);
+        }
+        frames
+    }
+
+    /// Ends the pending frame if nothing arrived for longer than the idle gap
+    /// before `now`; call it periodically so a final partial line shows up.
+    pub fn flush_idle(&mut self, now: Timestamp) -> Option<Frame> {
+        let gap = self.config.idle_gap?;
+        let last = self.last?;
+        if now.since(&last) > gap {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    /// Ends the pending frame regardless of the rules.
+    pub fn flush(&mut self) -> Option<Frame> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        Some(self.take(self.pending.len()))
+    }
+
+    /// Drops pending data, e.g. when the port is closed.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.start = None;
+        self.last = None;
+        self.searched = 0;
+    }
+
+    fn take(&mut self, end: usize) -> Frame {
+        let data: Vec<u8> = self.pending.drain(..end).collect();
+        self.searched = 0;
+        Frame {
+            data,
+            at: self.start.unwrap_or_else(Timestamp::now),
+        }
+    }
+
+    /// Length of the first complete frame in `pending`, if there is one.
+    fn find_end(&mut self) -> Option<usize> {
+        let buf = &self.pending;
+        let from = self.searched;
+        let (end, resume) = match &self.config.boundary {
+            FrameBoundary::None => (None, buf.len()),
+            FrameBoundary::Lf => find_byte(buf, from, b'\n'),
+            FrameBoundary::Cr => find_byte(buf, from, b'\r'),
+            FrameBoundary::CrLf => find_sequence(buf, from, b"\r\n"),
+            FrameBoundary::Sequence(seq) => find_sequence(buf, from, seq),
+            FrameBoundary::Auto => find_line_end(buf, from),
+            FrameBoundary::Length(n) if *n > 0 => ((buf.len() >= *n).then_some(*n), 0),
+            FrameBoundary::Length(_) => (None, buf.len()),
+        };
+        self.searched = resume;
+        end
+    }
+}
+
+fn find_byte(buf: &[u8], from: usize, byte: u8) -> (Option<usize>, usize) {
+    match buf[from..].iter().position(|&b| b == byte) {
+        Some(pos) => (Some(from + pos + 1), 0),
+        None => (None, buf.len()),
+    }
+}
+
+fn find_sequence(buf: &[u8], from: usize, seq: &[u8]) -> (Option<usize>, usize) {
+    if seq.is_empty() {
+        return (None, buf.len());
+    }
+    match buf[from..].windows(seq.len()).position(|w| w == seq) {
+        Some(pos) => (Some(from + pos + seq.len()), 0),
+        // A terminator may be split across chunks
+        None => (None, buf.len().saturating_sub(seq.len() - 1).max(from)),
+    }
+}
+
+fn find_line_end(buf: &[u8], from: usize) -> (Option<usize>, usize) {
+    for i in from..buf.len() {
+        match buf[i] {
+            b'\n' => return (Some(i + 1), 0),
+            // Whether an LF follows is only known once the next byte is in
+            b'\r' => match buf.get(i + 1) {
+                Some(b'\n') => return (Some(i + 2), 0),
+                Some(_) => return (Some(i + 1), 0),
+                None => return (None, i),
+            },
+            _ => {}
+        }
+    }
+    (None, buf.len())
+}
@@ -0,0 +1,101 @@
+//! Background watcher reporting serial ports as they appear and disappear.
+//!
+//! Polls the port list and diffs consecutive snapshots, which works the same
+//! on every platform `serialport` enumerates.
+
+use crate::serial_service::{PortInfo, SerialService};
+use crossbeam_channel::{bounded, unbounded, Receiver, RecvTimeoutError, Sender};
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PortEvent {
+    Added(PortInfo),
+    Removed(PortInfo),
+}
+
+pub struct PortWatcher {
+    events: Receiver<PortEvent>,
+    ports: Arc<Mutex<Vec<PortInfo>>>,
+    stop_tx: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PortWatcher {
+    /// Watches the system's serial ports, polling every `interval`.
+    pub fn start(interval: Duration) -> Self {
+        Self::with_source(interval, SerialService::list_ports)
+    }
+
+    /// Watches the lists returned by `source`. Ports present on the first
+    /// call are taken as the baseline and not reported.
+    pub fn with_source<F>(interval: Duration, mut source: F) -> Self
+    where
+        F: FnMut() -> Vec<PortInfo> + Send + 'static,
+    {
+        let (tx, events) = unbounded();
+        let (stop_tx, stop_rx) = bounded::<()>(1);
+        let ports = Arc::new(Mutex::new(source()));
+        let ports_clone = ports.clone();
+
+        let thread = std::thread::spawn(move || loop {
+            match stop_rx.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+            let current = source();
+            let changes = diff(&ports_clone.lock(), &current);
+            *ports_clone.lock() = current;
+            for change in changes {
+                if tx.send(change).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            events,
+            ports,
+            stop_tx: Some(stop_tx),
+            thread: Some(thread),
+        }
+    }
+
+    pub fn events(&self) -> &Receiver<PortEvent> {
+        &self.events
+    }
+
+    /// The most recent snapshot of the port list.
+    pub fn ports(&self) -> Vec<PortInfo> {
+        self.ports.lock().clone()
+    }
+
+    pub fn stop(&mut self) {
+        self.stop_tx.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for PortWatcher {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Changes turning `old` into `new`, removals first. A port that kept its name
+/// but changed identity counts as removed and added again.
+pub fn diff(old: &[PortInfo], new: &[PortInfo]) -> Vec<PortEvent> {
+    let removed = old
+        .iter()
+        .filter(|p| !new.contains(p))
+        .map(|p| PortEvent::Removed(p.clone()));
+    let added = new
+        .iter()
+        .filter(|p| !old.contains(p))
+        .map(|p| PortEvent::Added(p.clone()));
+    removed.chain(added).collect()
+}
@@ -1,10 +1,50 @@
 //! Core functionalities: serial I/O, logging buffer, settings.
 
+pub mod error;
 pub mod serial_service;
 pub mod logbuf;
 pub mod encoding;
+pub mod transport;
+pub mod mock;
+pub mod tcp;
+pub mod rfc2217;
+pub mod share;
+pub mod hotplug;
+pub mod pinseq;
+pub mod timestamp;
+pub mod txqueue;
+pub mod transaction;
+pub mod expect;
+pub mod framer;
+mod worker;
+#[cfg(feature = "tokio")]
+pub mod async_service;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+#[cfg(target_os = "linux")]
+pub mod pty;
 
-pub use serial_service::{SerialConfig, SerialEvent, SerialService, PortInfo, LineEnding, PinStates};
-pub use logbuf::{LogStore, LogEntry, Direction};
+pub use error::SerialError;
+pub use serial_service::{SerialConfig, SerialEvent, SerialService, SerialHandle, PortInfo, PortIdentity, LineEnding, PinStates, ModemLine, ReconnectPolicy};
+pub use logbuf::{LogStore, LogEntry, LogLine, LogLimits, Evicted, Direction, RenderOptions};
 pub use encoding::TextEncoding;
+pub use serialport::{DataBits, FlowControl, Parity, StopBits};
+pub use transport::{Transport, TransportReader, SerialPortTransport, Endpoint};
+pub use tcp::{TcpClientTransport, TcpServerTransport};
+pub use rfc2217::Rfc2217Transport;
+pub use share::{ShareServer, ShareConfig, ShareProtocol, ShareEvent, Permission, ClientInfo};
+pub use hotplug::{PortWatcher, PortEvent};
+pub use pinseq::{PinSequence, PinStep, ParsePinStepError};
+pub use timestamp::Timestamp;
+pub use txqueue::TxPacing;
+pub use transaction::{Transaction, TransactionResult, TransactionError, ResponseMatcher};
+pub use expect::{Expect, ExpectMatch, ExpectError, Script, ScriptProgress, ParseScriptError};
+pub use framer::{Framer, FramerConfig, FrameBoundary, Frame};
+#[cfg(feature = "tokio")]
+pub use async_service::{AsyncSerialService, EventStream};
+#[cfg(feature = "scripting")]
+pub use scripting::{ScriptHost, ScriptOutput, ScriptError};
+pub use mock::{MockPort, MockTransport, PinWiring};
+#[cfg(target_os = "linux")]
+pub use pty::{PtyPair, PtyTransport};
 
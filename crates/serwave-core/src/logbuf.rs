@@ -1,8 +1,11 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::encoding::TextEncoding;
+use crate::timestamp::Timestamp;
+use std::collections::VecDeque;
+use std::ops::Range;
 
 #[derive(Debug, Clone)]
 pub struct LogEntry {
-    pub timestamp: u64,
+    pub timestamp: Timestamp,
     pub direction: Direction,
     pub data: Vec<u8>,
 }
@@ -11,136 +14,319 @@ pub struct LogEntry {
 pub enum Direction {
     Rx,
     Tx,
+    /// Annotations such as connection changes; never filtered out.
+    Info,
+}
+
+impl Direction {
+    /// Marker in front of each line, in the log view and the log file alike.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Direction::Rx => "RX: ",
+            Direction::Tx => "TX: ",
+            Direction::Info => "-- ",
+        }
+    }
+}
+
+/// How much a `LogStore` keeps; the oldest entries go first once either is exceeded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogLimits {
+    pub max_entries: usize,
+    /// Total size of the entries' data.
+    pub max_bytes: usize,
+}
+
+impl Default for LogLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            max_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+/// What was dropped to stay within the limits since the store was created or cleared.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Evicted {
+    pub entries: u64,
+    pub bytes: u64,
+}
+
+/// How entries are turned into text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderOptions {
+    pub show_timestamp: bool,
+    pub show_hex: bool,
+    pub encoding: TextEncoding,
+}
+
+/// One rendered entry, without its line terminator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    pub direction: Direction,
+    pub text: String,
 }
 
 pub struct LogStore {
-    entries: Vec<LogEntry>,
-    max_entries: usize,
+    entries: VecDeque<LogEntry>,
+    /// Sequence number of `entries[0]`; every entry ever pushed gets the next one.
+    first_seq: u64,
+    /// Sequence numbers of the entries that pass the RX/TX filter, while one is active.
+    visible: Option<VecDeque<u64>>,
+    generation: u64,
+    limits: LogLimits,
+    bytes: usize,
+    evicted: Evicted,
+    /// Evicted entries that were visible rows at the time.
+    evicted_rows: u64,
     filter_rx: bool,
     filter_tx: bool,
     highlight_keywords: Vec<String>,
 }
 
 impl LogStore {
+    /// A store for up to `max_entries` entries and the default byte limit.
     pub fn new(max_entries: usize) -> Self {
-        Self {
-            entries: Vec::new(),
+        Self::with_limits(LogLimits {
             max_entries,
+            ..LogLimits::default()
+        })
+    }
+
+    pub fn with_limits(limits: LogLimits) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            first_seq: 0,
+            visible: None,
+            generation: 0,
+            limits,
+            bytes: 0,
+            evicted: Evicted::default(),
+            evicted_rows: 0,
             filter_rx: true,
             filter_tx: true,
             highlight_keywords: Vec::new(),
         }
     }
 
+    pub fn limits(&self) -> LogLimits {
+        self.limits
+    }
+
+    /// Applies new limits, evicting right away if the store is now over them.
+    pub fn set_limits(&mut self, limits: LogLimits) {
+        self.limits = limits;
+        self.evict();
+    }
+
+    pub fn evicted(&self) -> Evicted {
+        self.evicted
+    }
+
+    /// How many of the evicted entries were rows of the windowed view when
+    /// they went, so a view can drop exactly that many from its top.
+    pub fn evicted_rows(&self) -> u64 {
+        self.evicted_rows
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Total size of the stored entries' data.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
     pub fn set_filter(&mut self, show_rx: bool, show_tx: bool) {
         self.filter_rx = show_rx;
         self.filter_tx = show_tx;
+        self.visible = (!show_rx || !show_tx).then(|| {
+            let first_seq = self.first_seq;
+            let passes = |e: &LogEntry| self.passes_filter(e);
+            (first_seq..).zip(&self.entries).filter(|(_, e)| passes(e)).map(|(seq, _)| seq).collect()
+        });
+        self.generation += 1;
     }
 
     pub fn set_highlight_keywords(&mut self, keywords: Vec<String>) {
         self.highlight_keywords = keywords;
+        self.generation += 1;
+    }
+
+    /// Changes whenever what `render_window` would return may have changed:
+    /// new or evicted entries, filter or highlight changes, clearing.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Number of entries that pass the RX/TX filter, i.e. the rows of the windowed view.
+    pub fn visible_len(&self) -> usize {
+        self.visible.as_ref().map_or(self.entries.len(), |v| v.len())
+    }
+
+    /// Renders the visible entries in `range`, oldest first; the range is
+    /// clamped to `visible_len`. Only these entries are decoded, so a view
+    /// can show a huge log a screenful at a time. Lines match `to_text`.
+    pub fn render_window(&self, range: Range<usize>, options: &RenderOptions) -> Vec<LogLine> {
+        let end = range.end.min(self.visible_len());
+        (range.start.min(end)..end)
+            .filter_map(|row| self.visible_entry(row))
+            .map(|entry| LogLine {
+                direction: entry.direction,
+                text: self.render(entry, options).trim_end_matches(['\r', '\n']).to_string(),
+            })
+            .collect()
+    }
+
+    fn visible_entry(&self, row: usize) -> Option<&LogEntry> {
+        let index = match &self.visible {
+            Some(visible) => (*visible.get(row)? - self.first_seq) as usize,
+            None => row,
+        };
+        self.entries.get(index)
+    }
+
+    fn passes_filter(&self, entry: &LogEntry) -> bool {
+        match entry.direction {
+            Direction::Rx => self.filter_rx,
+            Direction::Tx => self.filter_tx,
+            Direction::Info => true,
+        }
     }
 
     pub fn push(&mut self, direction: Direction, data: Vec<u8>) {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64;
+        self.push_at(direction, data, Timestamp::now());
+    }
 
-        self.entries.push(LogEntry {
+    /// Like `push`, for data captured earlier, e.g. the `at` of a `SerialEvent::Rx`.
+    pub fn push_at(&mut self, direction: Direction, data: Vec<u8>, timestamp: Timestamp) {
+        let entry = LogEntry {
             timestamp,
             direction,
             data,
-        });
-
-        if self.entries.len() > self.max_entries {
-            self.entries.remove(0);
+        };
+        if self.passes_filter(&entry) {
+            let seq = self.first_seq + self.entries.len() as u64;
+            if let Some(visible) = &mut self.visible {
+                visible.push_back(seq);
+            }
         }
+        self.bytes += entry.data.len();
+        self.entries.push_back(entry);
+        self.generation += 1;
+        self.evict();
     }
 
-    pub fn entries(&self) -> &[LogEntry] {
+    /// Oldest first.
+    pub fn entries(&self) -> &VecDeque<LogEntry> {
         &self.entries
     }
 
     pub fn clear(&mut self) {
+        self.first_seq += self.entries.len() as u64;
         self.entries.clear();
+        if let Some(visible) = &mut self.visible {
+            visible.clear();
+        }
+        self.bytes = 0;
+        self.evicted = Evicted::default();
+        self.evicted_rows = 0;
+        self.generation += 1;
+    }
+
+    /// Drops the oldest entries until both limits hold again. The newest
+    /// entry is kept even if it alone is over the byte limit.
+    fn evict(&mut self) {
+        while self.entries.len() > self.limits.max_entries
+            || (self.bytes > self.limits.max_bytes && self.entries.len() > 1)
+        {
+            let Some(entry) = self.entries.pop_front() else { break };
+            let was_visible = match &mut self.visible {
+                Some(visible) if visible.front() == Some(&self.first_seq) => {
+                    visible.pop_front();
+                    true
+                }
+                Some(_) => false,
+                None => true,
+            };
+            if was_visible {
+                self.evicted_rows += 1;
+            }
+            self.first_seq += 1;
+            self.bytes -= entry.data.len();
+            self.evicted.entries += 1;
+            self.evicted.bytes += entry.data.len() as u64;
+            self.generation += 1;
+        }
     }
 
     pub fn to_text(&self, show_timestamp: bool, show_hex: bool) -> String {
-        self.to_text_with_encoding(show_timestamp, show_hex, crate::TextEncoding::Auto)
+        self.to_text_with_encoding(show_timestamp, show_hex, TextEncoding::Auto)
     }
 
-    pub fn to_text_with_encoding(&self, show_timestamp: bool, show_hex: bool, encoding: crate::TextEncoding) -> String {
+    /// The whole filtered log as one string, one line per entry. See
+    /// `render_window` for large logs.
+    pub fn to_text_with_encoding(&self, show_timestamp: bool, show_hex: bool, encoding: TextEncoding) -> String {
+        let options = RenderOptions { show_timestamp, show_hex, encoding };
         let mut result = String::new();
-        for entry in &self.entries {
-            if (entry.direction == Direction::Rx && !self.filter_rx) || (entry.direction == Direction::Tx && !self.filter_tx) {
-                continue;
+        for entry in self.entries.iter().filter(|e| self.passes_filter(e)) {
+            let line = self.render(entry, &options);
+            result.push_str(&line);
+            if !line.ends_with('\n') {
+                result.push('\n');
             }
+        }
+        result
+    }
 
-            let prefix = match entry.direction {
-                Direction::Rx => "RX: ",
-                Direction::Tx => "TX: ",
-            };
-
-            if show_hex {
-                if show_timestamp {
-                    let millis = entry.timestamp % 1000;
-                    let now = SystemTime::now();
-                    let elapsed = now.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
-                    let offset_ms = elapsed - entry.timestamp;
-                    let local_time = now - std::time::Duration::from_millis(offset_ms);
-
-                    if let Ok(duration) = local_time.duration_since(UNIX_EPOCH) {
-                        let total_secs = duration.as_secs();
-                        let local_secs = total_secs + (8 * 3600);
-                        let hours = (local_secs / 3600) % 24;
-                        let minutes = (local_secs / 60) % 60;
-                        let seconds = local_secs % 60;
-                        result.push_str(&format!("[{hours:02}:{minutes:02}:{seconds:02}.{millis:03}] "));
-                    }
-                }
-                result.push_str(prefix);
-                for byte in &entry.data {
-                    result.push_str(&format!("{byte:02X} "));
-                }
-                result.push('\n');
-            } else {
-                let mut text = encoding.decode(&entry.data);
-                if text.trim().is_empty() {
-                    continue;
-                }
+    /// Timestamp and direction prefix.
+    fn line_head(&self, entry: &LogEntry, options: &RenderOptions) -> String {
+        let mut line = String::new();
+        if options.show_timestamp {
+            line.push_str(&format_time(&entry.timestamp));
+        }
+        line.push_str(entry.direction.prefix());
+        line
+    }
 
-                if !self.highlight_keywords.is_empty() {
-                    for keyword in &self.highlight_keywords {
-                        if !keyword.is_empty() {
-                            text = text.replace(keyword, &format!("【{}】", keyword));
-                        }
-                    }
-                }
+    /// The entry as a line, keeping a terminator of its own. Text that
+    /// decodes to blanks, e.g. an empty line received, shows as just the
+    /// head, so every entry has a line and rows line up with entries.
+    fn render(&self, entry: &LogEntry, options: &RenderOptions) -> String {
+        let mut line = self.line_head(entry, options);
+        if options.show_hex {
+            for byte in &entry.data {
+                line.push_str(&format!("{byte:02X} "));
+            }
+            return line;
+        }
 
-                if show_timestamp {
-                    let millis = entry.timestamp % 1000;
-                    let now = SystemTime::now();
-                    let elapsed = now.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
-                    let offset_ms = elapsed - entry.timestamp;
-                    let local_time = now - std::time::Duration::from_millis(offset_ms);
-
-                    if let Ok(duration) = local_time.duration_since(UNIX_EPOCH) {
-                        let total_secs = duration.as_secs();
-                        let local_secs = total_secs + (8 * 3600);
-                        let hours = (local_secs / 3600) % 24;
-                        let minutes = (local_secs / 60) % 60;
-                        let seconds = local_secs % 60;
-                        result.push_str(&format!("[{hours:02}:{minutes:02}:{seconds:02}.{millis:03}] "));
-                    }
-                }
-                result.push_str(prefix);
-                result.push_str(&text);
-                if !text.ends_with('\n') {
-                    result.push('\n');
-                }
+        let mut text = options.encoding.decode(&entry.data);
+        if text.trim().is_empty() {
+            return line;
+        }
+        for keyword in &self.highlight_keywords {
+            if !keyword.is_empty() {
+                text = text.replace(keyword, &format!("【{}】", keyword));
             }
         }
-        result
+        line.push_str(&text);
+        line
     }
 }
+
+/// `[hh:mm:ss.uuuuuu] ` in UTC+8, with microseconds so gaps between entries can be read off directly.
+fn format_time(timestamp: &Timestamp) -> String {
+    let micros = timestamp.wall_micros();
+    let local_secs = micros / 1_000_000 + (8 * 3600);
+    let hours = (local_secs / 3600) % 24;
+    let minutes = (local_secs / 60) % 60;
+    let seconds = local_secs % 60;
+    format!("[{hours:02}:{minutes:02}:{seconds:02}.{:06}] ", micros % 1_000_000)
+}
@@ -0,0 +1,205 @@
+//! In-memory mock/loopback port for exercising `SerialService` consumers without hardware.
+
+use crate::error::SerialError;
+use crate::serial_service::{PinStates, SerialConfig, SerialService};
+use crate::transport::{Transport, TransportReader};
+use parking_lot::{Condvar, Mutex};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How the mock's output lines are wired back to its input lines.
+///
+/// The default mirrors a common loopback plug: RTS→CTS and DTR→DSR+DCD.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PinWiring {
+    pub rts_to_cts: bool,
+    pub dtr_to_dsr: bool,
+    pub dtr_to_dcd: bool,
+}
+
+impl Default for PinWiring {
+    fn default() -> Self {
+        Self {
+            rts_to_cts: true,
+            dtr_to_dsr: true,
+            dtr_to_dcd: true,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    rx: VecDeque<u8>,
+    written: Vec<u8>,
+    loopback: bool,
+    dtr: bool,
+    rts: bool,
+    brk: bool,
+    ri: bool,
+    wiring: PinWiring,
+    hung_up: bool,
+    line_settings: Option<SerialConfig>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<MockState>,
+    readable: Condvar,
+}
+
+/// Test-side handle of a mock port. Clones share the same port.
+#[derive(Clone, Default)]
+pub struct MockPort {
+    shared: Arc<Shared>,
+}
+
+impl MockPort {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A port whose written bytes are echoed back as RX.
+    pub fn loopback() -> Self {
+        let port = Self::new();
+        port.shared.state.lock().loopback = true;
+        port
+    }
+
+    /// Opens a `SerialService` on this port, as `SerialService::open` would on a device.
+    pub fn open(&self, cfg: SerialConfig) -> Result<SerialService, SerialError> {
+        SerialService::with_transport(cfg, Box::new(self.transport()))
+    }
+
+    pub fn transport(&self) -> MockTransport {
+        MockTransport { shared: self.shared.clone() }
+    }
+
+    /// Queues bytes to be delivered as RX.
+    pub fn inject_rx(&self, data: &[u8]) {
+        self.shared.state.lock().rx.extend(data);
+        self.shared.readable.notify_all();
+    }
+
+    /// Everything written so far, leaving the capture empty.
+    pub fn take_written(&self) -> Vec<u8> {
+        std::mem::take(&mut self.shared.state.lock().written)
+    }
+
+    pub fn written(&self) -> Vec<u8> {
+        self.shared.state.lock().written.clone()
+    }
+
+    pub fn dtr(&self) -> bool {
+        self.shared.state.lock().dtr
+    }
+
+    pub fn rts(&self) -> bool {
+        self.shared.state.lock().rts
+    }
+
+    /// Whether the port is currently held in a BREAK condition.
+    pub fn in_break(&self) -> bool {
+        self.shared.state.lock().brk
+    }
+
+    /// Line settings last applied through `Transport::reconfigure`.
+    pub fn line_settings(&self) -> Option<SerialConfig> {
+        self.shared.state.lock().line_settings.clone()
+    }
+
+    pub fn set_wiring(&self, wiring: PinWiring) {
+        self.shared.state.lock().wiring = wiring;
+    }
+
+    pub fn set_ring(&self, ri: bool) {
+        self.shared.state.lock().ri = ri;
+    }
+
+    /// Makes every further read and write fail as if the device vanished.
+    pub fn hang_up(&self) {
+        self.shared.state.lock().hung_up = true;
+        self.shared.readable.notify_all();
+    }
+}
+
+/// The `Transport` half of a [`MockPort`].
+pub struct MockTransport {
+    shared: Arc<Shared>,
+}
+
+fn hung_up() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "mock port hung up")
+}
+
+impl Transport for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.shared.state.lock();
+        if state.rx.is_empty() && !state.hung_up {
+            self.shared.readable.wait_for(&mut state, Duration::from_millis(50));
+        }
+        if state.hung_up {
+            return Err(hung_up());
+        }
+        let n = buf.len().min(state.rx.len());
+        for (dst, src) in buf.iter_mut().zip(state.rx.drain(..n)) {
+            *dst = src;
+        }
+        Ok(n)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut state = self.shared.state.lock();
+        if state.hung_up {
+            return Err(hung_up());
+        }
+        state.written.extend_from_slice(data);
+        if state.loopback {
+            state.rx.extend(data);
+            self.shared.readable.notify_all();
+        }
+        Ok(data.len())
+    }
+
+    fn set_dtr(&mut self, state: bool) -> io::Result<()> {
+        self.shared.state.lock().dtr = state;
+        Ok(())
+    }
+
+    fn set_rts(&mut self, state: bool) -> io::Result<()> {
+        self.shared.state.lock().rts = state;
+        Ok(())
+    }
+
+    fn set_break(&mut self, state: bool) -> io::Result<()> {
+        self.shared.state.lock().brk = state;
+        Ok(())
+    }
+
+    fn pin_states(&mut self) -> io::Result<PinStates> {
+        let state = self.shared.state.lock();
+        let wiring = state.wiring;
+        Ok(PinStates {
+            cts: wiring.rts_to_cts && state.rts,
+            dsr: wiring.dtr_to_dsr && state.dtr,
+            dcd: wiring.dtr_to_dcd && state.dtr,
+            ri: state.ri,
+        })
+    }
+
+    fn reconfigure(&mut self, cfg: &SerialConfig) -> io::Result<()> {
+        self.shared.state.lock().line_settings = Some(cfg.clone());
+        Ok(())
+    }
+
+    fn split_reader(&mut self) -> Option<Box<dyn TransportReader>> {
+        Some(Box::new(MockTransport { shared: self.shared.clone() }))
+    }
+}
+
+impl TransportReader for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Transport::read(self, buf)
+    }
+}
@@ -0,0 +1,134 @@
+//! Timed DTR/RTS sequences, e.g. for resetting a board into its bootloader.
+//!
+//! Sequences run inside the worker thread so the delays between steps are not
+//! stretched by the UI or channel latency.
+
+use std::fmt;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PinStep {
+    Dtr(bool),
+    Rts(bool),
+    Delay(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PinSequence {
+    pub name: String,
+    pub steps: Vec<PinStep>,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("invalid pin step \"{0}\"")]
+pub struct ParsePinStepError(String);
+
+impl PinSequence {
+    pub fn new(name: impl Into<String>, steps: Vec<PinStep>) -> Self {
+        Self { name: name.into(), steps }
+    }
+
+    /// esptool's classic reset for boards with the usual two-transistor
+    /// auto-reset circuit (RTS drives EN, DTR drives GPIO0): ends in the ROM
+    /// download mode.
+    pub fn esp32_classic_reset() -> Self {
+        Self::new(
+            "ESP32 classic reset",
+            vec![
+                PinStep::Dtr(false),
+                PinStep::Rts(true),
+                PinStep::Delay(Duration::from_millis(100)),
+                PinStep::Dtr(true),
+                PinStep::Rts(false),
+                PinStep::Delay(Duration::from_millis(50)),
+                PinStep::Dtr(false),
+            ],
+        )
+    }
+
+    /// Pulses RTS, which resets most auto-reset boards into their application.
+    pub fn hard_reset() -> Self {
+        Self::new(
+            "Hard reset",
+            vec![
+                PinStep::Rts(true),
+                PinStep::Delay(Duration::from_millis(100)),
+                PinStep::Rts(false),
+            ],
+        )
+    }
+
+    pub fn builtins() -> Vec<Self> {
+        vec![Self::esp32_classic_reset(), Self::hard_reset()]
+    }
+
+    /// Parses the text form used by [`fmt::Display`]: steps separated by `;`
+    /// or newlines, each a delay such as `100ms` or one or more assignments
+    /// such as `DTR=1 RTS=0`.
+    pub fn parse(name: impl Into<String>, text: &str) -> Result<Self, ParsePinStepError> {
+        let mut steps = Vec::new();
+        for group in text.split([';', '\n']).map(str::trim).filter(|g| !g.is_empty()) {
+            if let Some(ms) = group.strip_suffix("ms") {
+                let ms = ms.trim().parse().map_err(|_| ParsePinStepError(group.to_string()))?;
+                steps.push(PinStep::Delay(Duration::from_millis(ms)));
+                continue;
+            }
+            for assignment in group.split_whitespace() {
+                let step = match assignment.to_ascii_uppercase().as_str() {
+                    "DTR=1" => PinStep::Dtr(true),
+                    "DTR=0" => PinStep::Dtr(false),
+                    "RTS=1" => PinStep::Rts(true),
+                    "RTS=0" => PinStep::Rts(false),
+                    _ => return Err(ParsePinStepError(assignment.to_string())),
+                };
+                steps.push(step);
+            }
+        }
+        Ok(Self::new(name, steps))
+    }
+
+    /// Line states once the sequence has run, for steps that touch them.
+    pub fn final_states(&self) -> (Option<bool>, Option<bool>) {
+        let mut dtr = None;
+        let mut rts = None;
+        for step in &self.steps {
+            match *step {
+                PinStep::Dtr(state) => dtr = Some(state),
+                PinStep::Rts(state) => rts = Some(state),
+                PinStep::Delay(_) => {}
+            }
+        }
+        (dtr, rts)
+    }
+}
+
+impl fmt::Display for PinSequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        let mut in_group = false;
+        for step in &self.steps {
+            match step {
+                PinStep::Delay(delay) => {
+                    if !first {
+                        f.write_str("; ")?;
+                    }
+                    write!(f, "{}ms", delay.as_millis())?;
+                    in_group = false;
+                }
+                PinStep::Dtr(state) | PinStep::Rts(state) => {
+                    if in_group {
+                        f.write_str(" ")?;
+                    } else if !first {
+                        f.write_str("; ")?;
+                    }
+                    let line = if matches!(step, PinStep::Dtr(_)) { "DTR" } else { "RTS" };
+                    write!(f, "{}={}", line, u8::from(*state))?;
+                    in_group = true;
+                }
+            }
+            first = false;
+        }
+        Ok(())
+    }
+}
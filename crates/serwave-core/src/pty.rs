@@ -0,0 +1,144 @@
+//! Pseudo-terminal pairs acting as virtual serial ports.
+//!
+//! The slave side behaves like a regular tty device and can be opened through
+//! a normal `SerialConfig`; the master side plays the remote device.
+
+use crate::serial_service::SerialConfig;
+use crate::transport::{Transport, TransportReader};
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+pub struct PtyPair {
+    master: File,
+    // Held open so the master does not see EIO before anyone opens the slave.
+    _slave: OwnedFd,
+    slave_path: String,
+}
+
+impl PtyPair {
+    pub fn open() -> io::Result<Self> {
+        let mut master: libc::c_int = -1;
+        let mut slave: libc::c_int = -1;
+        let rc = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                std::ptr::null(),
+            )
+        };
+        if rc != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let master = unsafe { File::from_raw_fd(master) };
+        let slave = unsafe { OwnedFd::from_raw_fd(slave) };
+
+        let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+        if unsafe { libc::tcgetattr(slave.as_raw_fd(), termios.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut termios = unsafe { termios.assume_init() };
+        unsafe { libc::cfmakeraw(&mut termios) };
+        if unsafe { libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let name = unsafe { libc::ptsname(master.as_raw_fd()) };
+        if name.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let slave_path = unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned();
+
+        Ok(Self { master, _slave: slave, slave_path })
+    }
+
+    /// Device path of the slave side, e.g. `/dev/pts/3`.
+    pub fn slave_path(&self) -> &str {
+        &self.slave_path
+    }
+
+    /// A default `SerialConfig` pointing at the slave side.
+    pub fn config(&self) -> SerialConfig {
+        SerialConfig {
+            port_name: self.slave_path.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn master(&mut self) -> &mut File {
+        &mut self.master
+    }
+
+    /// Wraps the master side as a `Transport`, so a `SerialService` can act as the device.
+    pub fn into_master_transport(self) -> PtyTransport {
+        PtyTransport { pair: self }
+    }
+
+    /// Closes the master side, which the slave observes as a hangup.
+    pub fn hang_up(self) {
+        drop(self);
+    }
+}
+
+impl Read for PtyPair {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.master.read(buf)
+    }
+}
+
+impl Write for PtyPair {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.master.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.master.flush()
+    }
+}
+
+/// The master side of a [`PtyPair`] driven as a transport.
+pub struct PtyTransport {
+    pair: PtyPair,
+}
+
+/// Waits up to 50ms for `master` to become readable, then reads.
+fn poll_read(master: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut fds = libc::pollfd {
+        fd: master.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    match unsafe { libc::poll(&mut fds, 1, 50) } {
+        0 => Ok(0),
+        n if n < 0 => Err(io::Error::last_os_error()),
+        _ => master.read(buf),
+    }
+}
+
+struct PtyReader {
+    master: File,
+}
+
+impl TransportReader for PtyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        poll_read(&mut self.master, buf)
+    }
+}
+
+impl Transport for PtyTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        poll_read(&mut self.pair.master, buf)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.pair.master.write(data)
+    }
+
+    fn split_reader(&mut self) -> Option<Box<dyn TransportReader>> {
+        let master = self.pair.master.try_clone().ok()?;
+        Some(Box::new(PtyReader { master }))
+    }
+}
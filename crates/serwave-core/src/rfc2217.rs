@@ -0,0 +1,533 @@
+//! RFC 2217 (Telnet COM-port control) client transport.
+
+use crate::serial_service::{PinStates, SerialConfig};
+use crate::tcp;
+use crate::transport::{Transport, TransportReader};
+use parking_lot::{Condvar, Mutex};
+use serialport::{DataBits, FlowControl, Parity, StopBits};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub(crate) const IAC: u8 = 255;
+pub(crate) const DONT: u8 = 254;
+pub(crate) const DO: u8 = 253;
+pub(crate) const WONT: u8 = 252;
+pub(crate) const WILL: u8 = 251;
+pub(crate) const SB: u8 = 250;
+pub(crate) const SE: u8 = 240;
+
+pub(crate) const BINARY: u8 = 0;
+pub(crate) const SGA: u8 = 3;
+pub(crate) const COM_PORT_OPTION: u8 = 44;
+
+pub(crate) const SET_BAUDRATE: u8 = 1;
+pub(crate) const SET_DATASIZE: u8 = 2;
+pub(crate) const SET_PARITY: u8 = 3;
+pub(crate) const SET_STOPSIZE: u8 = 4;
+pub(crate) const SET_CONTROL: u8 = 5;
+pub(crate) const NOTIFY_MODEMSTATE: u8 = 7;
+pub(crate) const SET_MODEMSTATE_MASK: u8 = 11;
+/// Server replies use the client command code plus this offset.
+pub(crate) const SERVER_OFFSET: u8 = 100;
+
+pub(crate) const CONTROL_FLOW_NONE: u8 = 1;
+pub(crate) const CONTROL_FLOW_SOFTWARE: u8 = 2;
+pub(crate) const CONTROL_FLOW_HARDWARE: u8 = 3;
+pub(crate) const CONTROL_BREAK_ON: u8 = 5;
+pub(crate) const CONTROL_BREAK_OFF: u8 = 6;
+pub(crate) const CONTROL_DTR_ON: u8 = 8;
+pub(crate) const CONTROL_DTR_OFF: u8 = 9;
+pub(crate) const CONTROL_RTS_ON: u8 = 11;
+pub(crate) const CONTROL_RTS_OFF: u8 = 12;
+
+pub(crate) const MODEM_CTS: u8 = 0x10;
+pub(crate) const MODEM_DSR: u8 = 0x20;
+pub(crate) const MODEM_RI: u8 = 0x40;
+pub(crate) const MODEM_CD: u8 = 0x80;
+
+const ACK_TIMEOUT: Duration = Duration::from_secs(1);
+const MODEM_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const MODEM_POLL_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// One unit of an incoming Telnet stream.
+#[derive(Debug, PartialEq)]
+pub(crate) enum TelnetEvent {
+    Data(u8),
+    Negotiate(u8, u8),
+    Sub(Vec<u8>),
+}
+
+#[derive(Default)]
+enum ParseState {
+    #[default]
+    Data,
+    Iac,
+    Negotiate(u8),
+    Sub,
+    SubIac,
+}
+
+/// Incremental Telnet decoder: unescapes data and splits out commands.
+#[derive(Default)]
+pub(crate) struct TelnetParser {
+    state: ParseState,
+    sub: Vec<u8>,
+}
+
+impl TelnetParser {
+    pub(crate) fn feed(&mut self, bytes: &[u8], mut emit: impl FnMut(TelnetEvent)) {
+        for &b in bytes {
+            self.state = match std::mem::take(&mut self.state) {
+                ParseState::Data if b == IAC => ParseState::Iac,
+                ParseState::Data => {
+                    emit(TelnetEvent::Data(b));
+                    ParseState::Data
+                }
+                ParseState::Iac => match b {
+                    IAC => {
+                        emit(TelnetEvent::Data(IAC));
+                        ParseState::Data
+                    }
+                    WILL | WONT | DO | DONT => ParseState::Negotiate(b),
+                    SB => {
+                        self.sub.clear();
+                        ParseState::Sub
+                    }
+                    _ => ParseState::Data,
+                },
+                ParseState::Negotiate(cmd) => {
+                    emit(TelnetEvent::Negotiate(cmd, b));
+                    ParseState::Data
+                }
+                ParseState::Sub if b == IAC => ParseState::SubIac,
+                ParseState::Sub => {
+                    self.sub.push(b);
+                    ParseState::Sub
+                }
+                ParseState::SubIac => match b {
+                    SE => {
+                        emit(TelnetEvent::Sub(std::mem::take(&mut self.sub)));
+                        ParseState::Data
+                    }
+                    _ => {
+                        self.sub.push(b);
+                        ParseState::Sub
+                    }
+                },
+            };
+        }
+    }
+}
+
+/// Doubles every IAC byte so `data` survives a Telnet stream.
+pub(crate) fn escape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for &b in data {
+        out.push(b);
+        if b == IAC {
+            out.push(IAC);
+        }
+    }
+    out
+}
+
+pub(crate) fn subnegotiation(cmd: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![IAC, SB, COM_PORT_OPTION, cmd];
+    out.extend(escape(value));
+    out.extend([IAC, SE]);
+    out
+}
+
+pub(crate) fn parity_code(parity: Parity) -> u8 {
+    match parity {
+        Parity::None => 1,
+        Parity::Odd => 2,
+        Parity::Even => 3,
+    }
+}
+
+pub(crate) fn stop_bits_code(stop_bits: StopBits) -> u8 {
+    match stop_bits {
+        StopBits::One => 1,
+        StopBits::Two => 2,
+    }
+}
+
+pub(crate) fn flow_control_code(flow_control: FlowControl) -> u8 {
+    match flow_control {
+        FlowControl::None => CONTROL_FLOW_NONE,
+        FlowControl::Software => CONTROL_FLOW_SOFTWARE,
+        FlowControl::Hardware => CONTROL_FLOW_HARDWARE,
+    }
+}
+
+pub(crate) fn data_bits_code(data_bits: DataBits) -> u8 {
+    u8::from(data_bits)
+}
+
+pub(crate) fn parity_from_code(code: u8) -> Option<Parity> {
+    match code {
+        1 => Some(Parity::None),
+        2 => Some(Parity::Odd),
+        3 => Some(Parity::Even),
+        _ => None,
+    }
+}
+
+pub(crate) fn stop_bits_from_code(code: u8) -> Option<StopBits> {
+    match code {
+        1 => Some(StopBits::One),
+        2 => Some(StopBits::Two),
+        _ => None,
+    }
+}
+
+pub(crate) fn flow_control_from_code(code: u8) -> Option<FlowControl> {
+    match code {
+        CONTROL_FLOW_NONE => Some(FlowControl::None),
+        CONTROL_FLOW_SOFTWARE => Some(FlowControl::Software),
+        CONTROL_FLOW_HARDWARE => Some(FlowControl::Hardware),
+        _ => None,
+    }
+}
+
+pub(crate) fn modem_to_pins(state: u8) -> PinStates {
+    PinStates {
+        cts: state & MODEM_CTS != 0,
+        dsr: state & MODEM_DSR != 0,
+        dcd: state & MODEM_CD != 0,
+        ri: state & MODEM_RI != 0,
+    }
+}
+
+/// What the reading side has learnt from the server.
+#[derive(Default)]
+struct Remote {
+    acks: HashMap<u8, Vec<u8>>,
+    com_port_accepted: bool,
+    modem_state: Option<u8>,
+    /// Set once the reading side stopped; waiting for the server is pointless then.
+    lost: Option<io::ErrorKind>,
+}
+
+/// State shared between the transport and its reader, which may run on
+/// another thread after `split_reader`.
+struct Session {
+    remote: Mutex<Remote>,
+    changed: Condvar,
+    /// Data, commands and negotiation replies are written through one handle
+    /// so they never interleave.
+    writer: Mutex<TcpStream>,
+}
+
+impl Session {
+    fn write(&self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.lock().write_all(bytes)
+    }
+}
+
+/// Talks to a remote RFC 2217 port server such as ser2net or `esp_rfc2217_server`.
+///
+/// Line settings from the `SerialConfig` are negotiated when connecting, and
+/// DTR/RTS/modem-line access is forwarded to the remote port.
+pub struct Rfc2217Transport {
+    session: Arc<Session>,
+    /// Reads on the calling thread until it is handed to a reader thread.
+    reader: Option<Rfc2217Reader>,
+    last_modem_poll: Option<Instant>,
+    /// A poll sent by `sample_pin_states` whose answer has not been seen yet
+    modem_poll_pending: bool,
+    // Cleared when a poll goes unanswered; we then rely on unsolicited notifications only
+    modem_poll_answered: bool,
+}
+
+impl Rfc2217Transport {
+    pub fn connect(addr: &str, cfg: &SerialConfig) -> io::Result<Self> {
+        let stream = tcp::connect_stream(addr)?;
+        let session = Arc::new(Session {
+            remote: Mutex::new(Remote::default()),
+            changed: Condvar::new(),
+            writer: Mutex::new(stream.try_clone()?),
+        });
+        let mut transport = Self {
+            reader: Some(Rfc2217Reader {
+                stream,
+                parser: TelnetParser::default(),
+                pending: Vec::new(),
+                session: session.clone(),
+            }),
+            session,
+            last_modem_poll: None,
+            modem_poll_pending: false,
+            modem_poll_answered: true,
+        };
+
+        transport.session.write(&[
+            IAC, WILL, BINARY,
+            IAC, DO, BINARY,
+            IAC, WILL, SGA,
+            IAC, DO, SGA,
+            IAC, WILL, COM_PORT_OPTION,
+        ])?;
+        if !transport.wait_for(ACK_TIMEOUT, |remote| remote.com_port_accepted)? {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "remote does not support RFC 2217"));
+        }
+
+        transport.apply_settings(cfg)?;
+        transport.send_sub(SET_MODEMSTATE_MASK, &[0xFF])?;
+        Ok(transport)
+    }
+
+    /// Pushes baud rate, data bits, parity, stop bits and flow control to the remote port.
+    pub fn apply_settings(&mut self, cfg: &SerialConfig) -> io::Result<()> {
+        self.request(SET_BAUDRATE, &cfg.baud_rate.to_be_bytes())?;
+        self.request(SET_DATASIZE, &[data_bits_code(cfg.data_bits)])?;
+        self.request(SET_PARITY, &[parity_code(cfg.parity)])?;
+        self.request(SET_STOPSIZE, &[stop_bits_code(cfg.stop_bits)])?;
+        self.request(SET_CONTROL, &[flow_control_code(cfg.flow_control)])?;
+        Ok(())
+    }
+
+    fn send_sub(&mut self, cmd: u8, value: &[u8]) -> io::Result<()> {
+        self.session.write(&subnegotiation(cmd, value))
+    }
+
+    /// Sends a COM-port command and waits for the server to acknowledge it.
+    fn request(&mut self, cmd: u8, value: &[u8]) -> io::Result<Vec<u8>> {
+        self.session.remote.lock().acks.remove(&cmd);
+        self.send_sub(cmd, value)?;
+        let mut ack = None;
+        if self.wait_for(ACK_TIMEOUT, |remote| {
+            ack = remote.acks.remove(&cmd);
+            ack.is_some()
+        })? {
+            return Ok(ack.unwrap_or_default());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("remote did not acknowledge COM-port command {cmd}"),
+        ))
+    }
+
+    /// Waits until `done` holds for what the server sent, for at most
+    /// `timeout`; `false` if it did not. Reads itself until the reader is
+    /// split off, then waits for the reader thread.
+    fn wait_for(&mut self, timeout: Duration, mut done: impl FnMut(&mut Remote) -> bool) -> io::Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let mut remote = self.session.remote.lock();
+            if done(&mut remote) {
+                return Ok(true);
+            }
+            if let Some(kind) = remote.lost {
+                return Err(io::Error::new(kind, "RFC 2217 connection is no longer read"));
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            match &mut self.reader {
+                Some(reader) => {
+                    drop(remote);
+                    reader.pump()?;
+                }
+                None => {
+                    self.session.changed.wait_until(&mut remote, deadline);
+                }
+            }
+        }
+    }
+
+    fn modem_poll_due(&self) -> bool {
+        self.modem_poll_answered && self.last_modem_poll.is_none_or(|t| t.elapsed() >= MODEM_POLL_INTERVAL)
+    }
+
+    fn modem_poll_unanswered(&mut self) {
+        log::warn!("RFC 2217 server does not answer modem state polls");
+        self.modem_poll_answered = false;
+    }
+
+    fn set_control(&mut self, value: u8) -> io::Result<()> {
+        self.request(SET_CONTROL, &[value])?;
+        // Inputs wired to DTR/RTS may follow, so the next `pin_states` asks afresh
+        self.last_modem_poll = None;
+        Ok(())
+    }
+}
+
+/// The receiving side: unescapes data and records the server's commands.
+struct Rfc2217Reader {
+    stream: TcpStream,
+    parser: TelnetParser,
+    pending: Vec<u8>,
+    session: Arc<Session>,
+}
+
+impl Rfc2217Reader {
+    /// Reads once from the socket and processes whatever arrived.
+    fn pump(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 4096];
+        let n = match self.stream.read(&mut buf) {
+            Ok(0) => {
+                let e = io::Error::new(io::ErrorKind::ConnectionAborted, "connection closed by peer");
+                return Err(self.lost(e));
+            }
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(()),
+            Err(e) => return Err(self.lost(e)),
+        };
+
+        let mut events = Vec::new();
+        self.parser.feed(&buf[..n], |ev| events.push(ev));
+        let mut reply = Vec::new();
+        {
+            let mut remote = self.session.remote.lock();
+            for ev in events {
+                match ev {
+                    TelnetEvent::Data(b) => self.pending.push(b),
+                    TelnetEvent::Negotiate(cmd, opt) => negotiate(&mut remote, cmd, opt, &mut reply),
+                    TelnetEvent::Sub(sub) => handle_sub(&mut remote, &sub),
+                }
+            }
+        }
+        self.session.changed.notify_all();
+        if !reply.is_empty() {
+            self.session.write(&reply)?;
+        }
+        Ok(())
+    }
+
+    /// Wakes anyone waiting for the server and hands `e` on.
+    fn lost(&self, e: io::Error) -> io::Error {
+        self.session.remote.lock().lost.get_or_insert(e.kind());
+        self.session.changed.notify_all();
+        e
+    }
+}
+
+impl TransportReader for Rfc2217Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            self.pump()?;
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Drop for Rfc2217Reader {
+    fn drop(&mut self) {
+        // Nobody reads acknowledgements any more
+        self.lost(io::Error::from(io::ErrorKind::NotConnected));
+    }
+}
+
+fn negotiate(remote: &mut Remote, cmd: u8, opt: u8, reply: &mut Vec<u8>) {
+    let supported = matches!(opt, BINARY | SGA | COM_PORT_OPTION);
+    match cmd {
+        DO if opt == COM_PORT_OPTION => remote.com_port_accepted = true,
+        // We already announced WILL/DO for everything we support
+        DO | WILL if supported => {}
+        DO => reply.extend([IAC, WONT, opt]),
+        WILL => reply.extend([IAC, DONT, opt]),
+        DONT if opt == COM_PORT_OPTION => remote.com_port_accepted = false,
+        _ => {}
+    }
+}
+
+fn handle_sub(remote: &mut Remote, sub: &[u8]) {
+    let [COM_PORT_OPTION, code, value @ ..] = sub else {
+        return;
+    };
+    let Some(cmd) = code.checked_sub(SERVER_OFFSET) else {
+        return;
+    };
+    if cmd == NOTIFY_MODEMSTATE {
+        if let Some(&state) = value.first() {
+            remote.modem_state = Some(state);
+        }
+    }
+    remote.acks.insert(cmd, value.to_vec());
+}
+
+impl Transport for Rfc2217Transport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.reader {
+            Some(reader) => reader.read(buf),
+            None => Err(io::Error::new(io::ErrorKind::Unsupported, "reading happens on the split reader")),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.session.write(&escape(data))?;
+        Ok(data.len())
+    }
+
+    fn set_dtr(&mut self, state: bool) -> io::Result<()> {
+        self.set_control(if state { CONTROL_DTR_ON } else { CONTROL_DTR_OFF })
+    }
+
+    fn set_rts(&mut self, state: bool) -> io::Result<()> {
+        self.set_control(if state { CONTROL_RTS_ON } else { CONTROL_RTS_OFF })
+    }
+
+    fn set_break(&mut self, state: bool) -> io::Result<()> {
+        self.set_control(if state { CONTROL_BREAK_ON } else { CONTROL_BREAK_OFF })
+    }
+
+    fn pin_states(&mut self) -> io::Result<PinStates> {
+        if self.modem_poll_due() {
+            self.last_modem_poll = Some(Instant::now());
+            self.modem_poll_pending = false;
+            self.session.remote.lock().acks.remove(&NOTIFY_MODEMSTATE);
+            self.send_sub(NOTIFY_MODEMSTATE, &[])?;
+            if !self.wait_for(MODEM_POLL_TIMEOUT, |remote| remote.acks.contains_key(&NOTIFY_MODEMSTATE))? {
+                self.modem_poll_unanswered();
+            }
+        }
+        // Between polls the state comes from notifications picked up by the reader
+        Ok(modem_to_pins(self.session.remote.lock().modem_state.unwrap_or(0)))
+    }
+
+    /// Like `pin_states`, but sends the poll without waiting for it: the
+    /// answer is picked up by the reader and shows in a later sample.
+    fn sample_pin_states(&mut self) -> io::Result<PinStates> {
+        let (state, answered) = {
+            let remote = self.session.remote.lock();
+            (remote.modem_state.unwrap_or(0), remote.acks.contains_key(&NOTIFY_MODEMSTATE))
+        };
+        if self.modem_poll_pending {
+            if answered {
+                self.modem_poll_pending = false;
+            } else if self.last_modem_poll.is_some_and(|t| t.elapsed() >= MODEM_POLL_TIMEOUT) {
+                self.modem_poll_pending = false;
+                self.modem_poll_unanswered();
+            }
+        }
+        if !self.modem_poll_pending && self.modem_poll_due() {
+            self.last_modem_poll = Some(Instant::now());
+            self.modem_poll_pending = true;
+            self.session.remote.lock().acks.remove(&NOTIFY_MODEMSTATE);
+            self.send_sub(NOTIFY_MODEMSTATE, &[])?;
+        }
+        Ok(modem_to_pins(state))
+    }
+
+    fn reconfigure(&mut self, cfg: &SerialConfig) -> io::Result<()> {
+        self.apply_settings(cfg)
+    }
+
+    /// Hands reading, including the server's acknowledgements, to a reader
+    /// thread; commands then wait for it instead of reading themselves.
+    fn split_reader(&mut self) -> Option<Box<dyn TransportReader>> {
+        self.reader.take().map(|reader| Box::new(reader) as Box<dyn TransportReader>)
+    }
+
+    fn close(&mut self) {
+        let _ = self.session.writer.lock().shutdown(std::net::Shutdown::Both);
+    }
+}
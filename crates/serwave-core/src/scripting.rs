@@ -0,0 +1,481 @@
+//! Embedded Rhai scripting, for automation that needs more than `expect`
+//! scripts: loops, functions, files, checksums and reacting to events.
+//!
+//! A script runs on its own thread against a `SerialHandle`. Its top-level
+//! code runs first; if it defines `on_rx(data)` or `on_event(event)`, or has
+//! started timers, the host then keeps calling those until the script calls
+//! `exit()`, is stopped or the port closes. Top-level variables stay visible
+//! to handlers, so they can keep state between calls.
+//!
+//! ```text
+//! let received = 0;
+//! fn on_rx(data) { received += data.len(); }
+//! fn report() { log(`${received} bytes so far`); }
+//!
+//! set_dtr(false);
+//! send("status\r\n");
+//! every(1000, "report");
+//! ```
+//!
+//! Bindings, besides the Rhai standard library:
+//!
+//! - `send(text)`, `send(blob)`, `send_hex("01 03 ff")` queue data for writing
+//! - `set_dtr(on)`, `set_rts(on)`, `send_break(ms)`, `pin_states()` (a map
+//!   with `cts`, `dsr`, `dcd`, `ri`)
+//! - `log(text)` adds an annotation to the session log; `print` goes to the
+//!   script console
+//! - `sleep(ms)`, `now_ms()`, `every(ms, "fn")`, `after(ms, "fn")`, `exit()`
+//! - `read_file(path)`, `read_blob(path)`, `write_file(path, data)`,
+//!   `append_file(path, data)`
+//! - `hex(blob)`, `crc16_modbus(blob)`, `crc32(blob)`, `sum8(blob)`, `xor8(blob)`
+//!
+//! `on_event` receives a map with a `kind` (`"rx"`, `"tx"`, `"pin"`,
+//! `"disconnected"`, ...) and the event's fields.
+
+use crate::serial_service::{Command, SerialEvent, SerialHandle};
+use crossbeam_channel::{bounded, select, unbounded, Receiver, Sender};
+use parking_lot::Mutex;
+use rhai::{Blob, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::fs;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+/// Longest the event loop blocks before looking at the stop flag again.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Error)]
+pub enum ScriptError {
+    #[error("{0}")]
+    Compile(String),
+    #[error("{0}")]
+    Runtime(String),
+    #[error("script was stopped")]
+    Stopped,
+}
+
+/// What a running script reports back to its host.
+#[derive(Debug, Clone)]
+pub enum ScriptOutput {
+    /// Output of `print` and `debug`.
+    Print(String),
+    /// An annotation for the session log, from `log`.
+    Log(String),
+    /// The script ended; always the last output.
+    Finished(Result<(), ScriptError>),
+}
+
+struct Timer {
+    due: Instant,
+    every: Option<Duration>,
+    function: String,
+}
+
+#[derive(Default)]
+struct State {
+    timers: Vec<Timer>,
+    exit: bool,
+}
+
+type RhaiResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// A Rhai script running against a connection.
+pub struct ScriptHost {
+    stop: Arc<AtomicBool>,
+    output: Receiver<ScriptOutput>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ScriptHost {
+    /// Compiles `source` and starts running it; compile errors are returned
+    /// right away, everything later arrives as `ScriptOutput`.
+    pub fn start(source: &str, handle: SerialHandle) -> Result<Self, ScriptError> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let state = Arc::new(Mutex::new(State::default()));
+        let (tx, output) = unbounded();
+        let engine = build_engine(handle.clone(), state.clone(), stop.clone(), tx.clone());
+        let ast = engine.compile(source).map_err(|e| ScriptError::Compile(e.to_string()))?;
+        // Subscribed up front so events during the top-level code reach the handlers
+        let events = handle.subscribe();
+        let thread = std::thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let result = run(&engine, &ast, &state, &stop, &events);
+                let _ = tx.send(ScriptOutput::Finished(result));
+            }
+        });
+        Ok(Self { stop, output, thread: Some(thread) })
+    }
+
+    /// Asks the script to stop; it ends with `ScriptError::Stopped`.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn output(&self) -> &Receiver<ScriptOutput> {
+        &self.output
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(|t| t.is_finished())
+    }
+}
+
+impl Drop for ScriptHost {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run(
+    engine: &Engine,
+    ast: &AST,
+    state: &Mutex<State>,
+    stop: &AtomicBool,
+    events: &Receiver<SerialEvent>,
+) -> Result<(), ScriptError> {
+    let mut scope = Scope::new();
+    engine.run_ast_with_scope(&mut scope, ast).map_err(|e| runtime(e, stop))?;
+
+    let has = |name: &str| ast.iter_functions().any(|f| f.name == name && f.params.len() == 1);
+    let (on_rx, on_event) = (has("on_rx"), has("on_event"));
+    let mut call = |name: &str, args: Vec<Dynamic>| -> Result<(), ScriptError> {
+        // The top-level code already ran; only the function itself runs now
+        let options = CallFnOptions::new().eval_ast(false);
+        engine
+            .call_fn_with_options::<Dynamic>(options, &mut scope, ast, name, args)
+            .map(|_| ())
+            .map_err(|e| runtime(e, stop))
+    };
+
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return Err(ScriptError::Stopped);
+        }
+        let next_due = {
+            let state = state.lock();
+            if state.exit || (!on_rx && !on_event && state.timers.is_empty()) {
+                return Ok(());
+            }
+            state.timers.iter().map(|t| t.due).min()
+        };
+        let wait = next_due.map_or(POLL_INTERVAL, |due| due.saturating_duration_since(Instant::now()).min(POLL_INTERVAL));
+
+        select! {
+            recv(events) -> event => {
+                let Ok(event) = event else { return Ok(()) };
+                let closed = matches!(event, SerialEvent::Closed);
+                if let (true, SerialEvent::Rx { data, .. }) = (on_rx, &event) {
+                    call("on_rx", vec![Dynamic::from_blob(data.clone())])?;
+                }
+                if on_event {
+                    call("on_event", vec![event_map(&event).into()])?;
+                }
+                if closed {
+                    return Ok(());
+                }
+            }
+            default(wait) => {}
+        }
+
+        for function in due_timers(state) {
+            call(&function, Vec::new())?;
+        }
+    }
+}
+
+/// Takes the timers that are due, rescheduling the repeating ones.
+fn due_timers(state: &Mutex<State>) -> Vec<String> {
+    let now = Instant::now();
+    let mut state = state.lock();
+    let mut due = Vec::new();
+    state.timers.retain_mut(|timer| {
+        if timer.due > now {
+            return true;
+        }
+        due.push(timer.function.clone());
+        match timer.every {
+            Some(every) => {
+                timer.due += every;
+                true
+            }
+            None => false,
+        }
+    });
+    due
+}
+
+fn runtime(err: Box<EvalAltResult>, stop: &AtomicBool) -> ScriptError {
+    if stop.load(Ordering::Relaxed) {
+        ScriptError::Stopped
+    } else {
+        ScriptError::Runtime(err.to_string())
+    }
+}
+
+fn event_map(event: &SerialEvent) -> Map {
+    let mut map = Map::new();
+    let mut set = |key: &str, value: Dynamic| {
+        map.insert(key.into(), value);
+    };
+    let kind = match event {
+        SerialEvent::Rx { data, .. } => {
+            set("data", Dynamic::from_blob(data.clone()));
+            "rx"
+        }
+        SerialEvent::Tx(n) => {
+            set("count", (*n as i64).into());
+            "tx"
+        }
+        SerialEvent::TxProgress { sent, total, queued } => {
+            set("sent", (*sent as i64).into());
+            set("total", (*total as i64).into());
+            set("queued", (*queued as i64).into());
+            "tx_progress"
+        }
+        SerialEvent::TxCancelled { dropped } => {
+            set("dropped", (*dropped as i64).into());
+            "tx_cancelled"
+        }
+        SerialEvent::Opened(port) => {
+            set("port", port.clone().into());
+            "opened"
+        }
+        SerialEvent::Closed => "closed",
+        SerialEvent::Error(e) => {
+            set("error", e.to_string().into());
+            "error"
+        }
+        SerialEvent::PinStates(states) => {
+            set("cts", states.cts.into());
+            set("dsr", states.dsr.into());
+            set("dcd", states.dcd.into());
+            set("ri", states.ri.into());
+            "pin_states"
+        }
+        SerialEvent::Disconnected(e) => {
+            set("error", e.to_string().into());
+            "disconnected"
+        }
+        SerialEvent::Reconnecting { attempt, delay } => {
+            set("attempt", (*attempt as i64).into());
+            set("delay_ms", (delay.as_millis() as i64).into());
+            "reconnecting"
+        }
+        SerialEvent::Reconnected(port) => {
+            set("port", port.clone().into());
+            "reconnected"
+        }
+        SerialEvent::Reconfigured(cfg) => {
+            set("baud_rate", (cfg.baud_rate as i64).into());
+            "reconfigured"
+        }
+        SerialEvent::Break(on) => {
+            set("on", (*on).into());
+            "break"
+        }
+        SerialEvent::PinSequenceDone(name) => {
+            set("name", name.clone().into());
+            "pin_sequence_done"
+        }
+        SerialEvent::PinChanged { line, state, .. } => {
+            set("line", line.to_string().into());
+            set("state", (*state).into());
+            "pin"
+        }
+    };
+    map.insert("kind".into(), kind.into());
+    map
+}
+
+fn build_engine(handle: SerialHandle, state: Arc<Mutex<State>>, stop: Arc<AtomicBool>, output: Sender<ScriptOutput>) -> Engine {
+    let mut engine = Engine::new();
+    {
+        let stop = stop.clone();
+        engine.on_progress(move |_| stop.load(Ordering::Relaxed).then_some(Dynamic::UNIT));
+    }
+    {
+        let output = output.clone();
+        engine.on_print(move |s| {
+            let _ = output.send(ScriptOutput::Print(s.to_string()));
+        });
+    }
+    {
+        let output = output.clone();
+        engine.on_debug(move |s, _, _| {
+            let _ = output.send(ScriptOutput::Print(s.to_string()));
+        });
+    }
+    engine.register_fn("log", move |text: &str| {
+        let _ = output.send(ScriptOutput::Log(text.to_string()));
+    });
+
+    // Port
+    {
+        let handle = handle.clone();
+        engine.register_fn("send", move |text: &str| -> RhaiResult<()> {
+            handle.send(text.as_bytes().to_vec()).map_err(|e| e.to_string().into())
+        });
+    }
+    {
+        let handle = handle.clone();
+        engine.register_fn("send", move |data: Blob| -> RhaiResult<()> {
+            handle.send(data).map_err(|e| e.to_string().into())
+        });
+    }
+    {
+        let handle = handle.clone();
+        engine.register_fn("send_hex", move |text: &str| -> RhaiResult<()> {
+            let data = parse_hex(text)?;
+            handle.send(data).map_err(|e| e.to_string().into())
+        });
+    }
+    {
+        let handle = handle.clone();
+        engine.register_fn("set_dtr", move |on: bool| -> RhaiResult<()> {
+            handle.set_dtr(on).map_err(|e| e.to_string().into())
+        });
+    }
+    {
+        let handle = handle.clone();
+        engine.register_fn("set_rts", move |on: bool| -> RhaiResult<()> {
+            handle.set_rts(on).map_err(|e| e.to_string().into())
+        });
+    }
+    {
+        let handle = handle.clone();
+        engine.register_fn("send_break", move |ms: i64| -> RhaiResult<()> {
+            handle.send_break(millis(ms)).map_err(|e| e.to_string().into())
+        });
+    }
+    engine.register_fn("pin_states", move || -> RhaiResult<Map> {
+        let (tx, rx) = bounded(1);
+        let reply = Box::new(move |result| {
+            let _ = tx.send(result);
+        });
+        handle.command(Command::GetPinStates(Some(reply))).map_err(|e| e.to_string())?;
+        let states = rx.recv().map_err(|_| "port closed".to_string())?.map_err(|e| e.to_string())?;
+        let mut map = Map::new();
+        map.insert("cts".into(), states.cts.into());
+        map.insert("dsr".into(), states.dsr.into());
+        map.insert("dcd".into(), states.dcd.into());
+        map.insert("ri".into(), states.ri.into());
+        Ok(map)
+    });
+
+    // Time
+    engine.register_fn("sleep", move |ms: i64| {
+        // In slices, so that stopping does not wait for a long sleep
+        let deadline = Instant::now() + millis(ms);
+        while !stop.load(Ordering::Relaxed) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            std::thread::sleep(left.min(POLL_INTERVAL));
+        }
+    });
+    engine.register_fn("now_ms", || {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as i64)
+    });
+    {
+        let state = state.clone();
+        engine.register_fn("every", move |ms: i64, function: &str| {
+            let every = millis(ms).max(Duration::from_millis(1));
+            state.lock().timers.push(Timer {
+                due: Instant::now() + every,
+                every: Some(every),
+                function: function.to_string(),
+            });
+        });
+    }
+    {
+        let state = state.clone();
+        engine.register_fn("after", move |ms: i64, function: &str| {
+            state.lock().timers.push(Timer {
+                due: Instant::now() + millis(ms),
+                every: None,
+                function: function.to_string(),
+            });
+        });
+    }
+    engine.register_fn("exit", move || {
+        let mut state = state.lock();
+        state.exit = true;
+        state.timers.clear();
+    });
+
+    // Files
+    engine.register_fn("read_file", |path: &str| -> RhaiResult<String> {
+        fs::read_to_string(path).map_err(|e| format!("{path}: {e}").into())
+    });
+    engine.register_fn("read_blob", |path: &str| -> RhaiResult<Blob> {
+        fs::read(path).map_err(|e| format!("{path}: {e}").into())
+    });
+    engine.register_fn("write_file", |path: &str, text: &str| write_file(path, text.as_bytes(), false));
+    engine.register_fn("write_file", |path: &str, data: Blob| write_file(path, &data, false));
+    engine.register_fn("append_file", |path: &str, text: &str| write_file(path, text.as_bytes(), true));
+    engine.register_fn("append_file", |path: &str, data: Blob| write_file(path, &data, true));
+
+    // Data
+    engine.register_fn("hex", |data: Blob| {
+        data.iter().map(|b| format!("{b:02X}")).collect::<Vec<_>>().join(" ")
+    });
+    engine.register_fn("crc16_modbus", |data: Blob| crc16_modbus(&data) as i64);
+    engine.register_fn("crc32", |data: Blob| crc32(&data) as i64);
+    engine.register_fn("sum8", |data: Blob| data.iter().fold(0u8, |acc, b| acc.wrapping_add(*b)) as i64);
+    engine.register_fn("xor8", |data: Blob| data.iter().fold(0u8, |acc, b| acc ^ b) as i64);
+    engine
+}
+
+fn millis(ms: i64) -> Duration {
+    Duration::from_millis(ms.max(0) as u64)
+}
+
+fn write_file(path: &str, data: &[u8], append: bool) -> RhaiResult<()> {
+    fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .and_then(|mut file| file.write_all(data))
+        .map_err(|e| format!("{path}: {e}").into())
+}
+
+/// "01 03 ff" or "0103FF" to bytes.
+fn parse_hex(text: &str) -> RhaiResult<Blob> {
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.is_ascii() || !digits.len().is_multiple_of(2) {
+        return Err(format!("bad hex in {text:?}").into());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| format!("bad hex in {text:?}").into()))
+        .collect()
+}
+
+fn crc16_modbus(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
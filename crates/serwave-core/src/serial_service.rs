@@ -1,9 +1,17 @@
+use crate::error::SerialError;
+use crate::pinseq::PinSequence;
+use crate::timestamp::Timestamp;
+use crate::txqueue::TxPacing;
+use crate::transport::{self, Endpoint, Transport};
+use crate::worker;
 use crossbeam_channel::{unbounded, Receiver, Sender};
+use parking_lot::Mutex;
 use serialport::SerialPortInfo;
-use std::io::{Read, Write};
+use std::sync::Arc;
+use std::fmt;
 use std::time::Duration;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PortInfo {
     pub port_name: String,
     pub port_type: String,
@@ -41,6 +49,32 @@ impl From<SerialPortInfo> for PortInfo {
     }
 }
 
+impl PortInfo {
+    /// USB identity of the device, which survives re-enumeration under a new name.
+    pub fn identity(&self) -> Option<PortIdentity> {
+        Some(PortIdentity {
+            vid: self.vid?,
+            pid: self.pid?,
+            serial_number: self.serial_number.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortIdentity {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+}
+
+impl PortIdentity {
+    pub fn matches(&self, info: &PortInfo) -> bool {
+        info.vid == Some(self.vid)
+            && info.pid == Some(self.pid)
+            && (self.serial_number.is_none() || info.serial_number == self.serial_number)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LineEnding {
     LF,
@@ -58,6 +92,27 @@ impl LineEnding {
     }
 }
 
+/// Backoff schedule for reopening a port after the device disappeared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: u32,
+    /// Give up after this many attempts; `None` retries until closed.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2,
+            max_attempts: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SerialConfig {
     pub port_name: String,
@@ -67,6 +122,15 @@ pub struct SerialConfig {
     pub stop_bits: serialport::StopBits,
     pub flow_control: serialport::FlowControl,
     pub line_ending: LineEnding,
+    pub reconnect: Option<ReconnectPolicy>,
+    /// Queue sends and write them slowly; `None` writes each send at once.
+    pub pacing: Option<TxPacing>,
+}
+
+impl SerialConfig {
+    pub fn endpoint(&self) -> Endpoint {
+        Endpoint::parse(&self.port_name)
+    }
 }
 
 impl Default for SerialConfig {
@@ -79,29 +143,80 @@ impl Default for SerialConfig {
             stop_bits: serialport::StopBits::One,
             flow_control: serialport::FlowControl::None,
             line_ending: LineEnding::LF,
+            reconnect: None,
+            pacing: None,
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum SerialEvent {
-    Rx(Vec<u8>),
+    /// Bytes from the port; `at` is taken as soon as the read returned.
+    Rx { data: Vec<u8>, at: Timestamp },
+    /// A send was written completely; paced sends report it once at the end.
     Tx(usize),
+    /// A paced write went out: `sent` of `total` bytes of the current send,
+    /// with `queued` bytes still waiting over all sends.
+    TxProgress { sent: usize, total: usize, queued: usize },
+    /// Queued sends were dropped by `cancel_tx` or a failed write.
+    TxCancelled { dropped: usize },
     Opened(String),
     Closed,
-    Error(String),
+    Error(SerialError),
     PinStates(PinStates),
+    /// The device went away; followed by `Reconnecting`/`Reconnected` when a
+    /// `ReconnectPolicy` is configured, or by `Closed` otherwise.
+    Disconnected(SerialError),
+    Reconnecting { attempt: u32, delay: Duration },
+    Reconnected(String),
+    /// New line settings are in effect, sent in reply to `reconfigure`.
+    Reconfigured(SerialConfig),
+    /// A BREAK condition started (`true`) or ended.
+    Break(bool),
+    /// The named pin sequence ran to completion.
+    PinSequenceDone(String),
+    /// A modem input line differs from the worker's previous sample; `at` is
+    /// when it noticed. The lines are sampled, every 10 ms while idle, not
+    /// watched for edges: a pulse between two samples goes unreported, and a
+    /// line that toggles several times in between shows as one change or none.
+    PinChanged { line: ModemLine, state: bool, at: Timestamp },
 }
 
-enum Command {
-    Send(Vec<u8>),
+/// Completion callback for a command, run on the worker thread. Events are
+/// emitted as usual; the reply additionally tells one caller how it went.
+pub(crate) type Reply<T> = Box<dyn FnOnce(Result<T, SerialError>) + Send>;
+
+pub(crate) fn answer<T>(reply: Option<Reply<T>>, result: Result<T, SerialError>) {
+    if let Some(reply) = reply {
+        reply(result);
+    }
+}
+
+/// What the reply to a `Send` reports once it went out completely.
+pub(crate) struct Written {
+    // Only the async `send` hands the length on
+    #[cfg_attr(not(feature = "tokio"), allow(dead_code))]
+    pub len: usize,
+    /// When the write that completed the send started, which is where a
+    /// response can first be caused.
+    pub at: Timestamp,
+}
+
+pub(crate) enum Command {
+    Send(Vec<u8>, Option<Reply<Written>>),
+    CancelTx,
     Close,
-    SetDtr(bool),
-    SetRts(bool),
-    GetPinStates,
+    SetDtr(bool, Option<Reply<()>>),
+    SetRts(bool, Option<Reply<()>>),
+    GetPinStates(Option<Reply<PinStates>>),
+    Reconfigure(SerialConfig),
+    SetPacing(Option<TxPacing>),
+    SetBreak(bool),
+    SendBreak(Duration),
+    RunPinSequence(PinSequence),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct PinStates {
     pub cts: bool,
     pub dsr: bool,
@@ -109,12 +224,150 @@ pub struct PinStates {
     pub ri: bool,
 }
 
+impl PinStates {
+    pub fn get(&self, line: ModemLine) -> bool {
+        match line {
+            ModemLine::Cts => self.cts,
+            ModemLine::Dsr => self.dsr,
+            ModemLine::Dcd => self.dcd,
+            ModemLine::Ri => self.ri,
+        }
+    }
+
+    pub fn set(&mut self, line: ModemLine, state: bool) {
+        match line {
+            ModemLine::Cts => self.cts = state,
+            ModemLine::Dsr => self.dsr = state,
+            ModemLine::Dcd => self.dcd = state,
+            ModemLine::Ri => self.ri = state,
+        }
+    }
+
+    /// Lines whose state differs between `self` and `other`.
+    pub fn changed_lines(&self, other: &PinStates) -> impl Iterator<Item = ModemLine> + '_ {
+        let other = *other;
+        ModemLine::ALL.into_iter().filter(move |&line| self.get(line) != other.get(line))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModemLine {
+    Cts,
+    Dsr,
+    Dcd,
+    Ri,
+}
+
+impl ModemLine {
+    pub const ALL: [ModemLine; 4] = [ModemLine::Cts, ModemLine::Dsr, ModemLine::Dcd, ModemLine::Ri];
+}
+
+impl fmt::Display for ModemLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ModemLine::Cts => "CTS",
+            ModemLine::Dsr => "DSR",
+            ModemLine::Dcd => "DCD",
+            ModemLine::Ri => "RI",
+        })
+    }
+}
+
+type Subscribers = Arc<Mutex<Vec<Sender<SerialEvent>>>>;
+
+/// Delivers worker events to the service owner and every subscriber.
+#[derive(Clone)]
+pub(crate) struct EventSink {
+    main: Sender<SerialEvent>,
+    subscribers: Subscribers,
+}
+
+impl EventSink {
+    pub(crate) fn emit(&self, event: SerialEvent) {
+        self.subscribers.lock().retain(|tx| tx.send(event.clone()).is_ok());
+        let _ = self.main.send(event);
+    }
+}
+
 pub struct SerialService {
-    cfg: SerialConfig,
-    tx_cmd: Sender<Command>,
+    handle: SerialHandle,
     rx_evt: Receiver<SerialEvent>,
 }
 
+/// Cloneable, thread-safe access to a running `SerialService`, for helpers
+/// that live on other threads (network sharing, scripts, ...).
+#[derive(Clone)]
+pub struct SerialHandle {
+    tx_cmd: Sender<Command>,
+    subscribers: Subscribers,
+    config: Arc<Mutex<SerialConfig>>,
+}
+
+impl SerialHandle {
+    pub fn send(&self, data: Vec<u8>) -> Result<(), SerialError> {
+        self.tx_cmd.send(Command::Send(data, None)).map_err(SerialError::from)
+    }
+
+    /// Drops paced sends that have not been written yet.
+    pub fn cancel_tx(&self) -> Result<(), SerialError> {
+        self.tx_cmd.send(Command::CancelTx).map_err(SerialError::from)
+    }
+
+    pub fn set_dtr(&self, state: bool) -> Result<(), SerialError> {
+        self.tx_cmd.send(Command::SetDtr(state, None)).map_err(SerialError::from)
+    }
+
+    pub fn set_rts(&self, state: bool) -> Result<(), SerialError> {
+        self.tx_cmd.send(Command::SetRts(state, None)).map_err(SerialError::from)
+    }
+
+    pub fn request_pin_states(&self) -> Result<(), SerialError> {
+        self.tx_cmd.send(Command::GetPinStates(None)).map_err(SerialError::from)
+    }
+
+    /// Holds the TX line in a BREAK condition until `set_break(false)`.
+    pub fn set_break(&self, state: bool) -> Result<(), SerialError> {
+        self.tx_cmd.send(Command::SetBreak(state)).map_err(SerialError::from)
+    }
+
+    /// Sends a BREAK of the given length; the worker keeps servicing RX meanwhile.
+    pub fn send_break(&self, duration: Duration) -> Result<(), SerialError> {
+        self.tx_cmd.send(Command::SendBreak(duration)).map_err(SerialError::from)
+    }
+
+    /// Runs `sequence` on the worker thread; other commands wait until it is done.
+    pub fn run_pin_sequence(&self, sequence: PinSequence) -> Result<(), SerialError> {
+        self.tx_cmd.send(Command::RunPinSequence(sequence)).map_err(SerialError::from)
+    }
+
+    /// Applies the line settings of `cfg` (baud rate, framing, flow control,
+    /// line ending) to the open port; its name and reconnect policy are ignored.
+    pub fn reconfigure(&self, cfg: SerialConfig) -> Result<(), SerialError> {
+        self.tx_cmd.send(Command::Reconfigure(cfg)).map_err(SerialError::from)
+    }
+
+    /// Changes how sends are paced, leaving the line settings alone. Sends
+    /// already queued continue at the new pace once the current pause is over.
+    pub fn set_pacing(&self, pacing: Option<TxPacing>) -> Result<(), SerialError> {
+        self.tx_cmd.send(Command::SetPacing(pacing)).map_err(SerialError::from)
+    }
+
+    /// The settings currently in effect.
+    pub fn config(&self) -> SerialConfig {
+        self.config.lock().clone()
+    }
+
+    pub fn subscribe(&self) -> Receiver<SerialEvent> {
+        let (tx, rx) = unbounded();
+        self.subscribers.lock().push(tx);
+        rx
+    }
+
+    pub(crate) fn command(&self, cmd: Command) -> Result<(), SerialError> {
+        self.tx_cmd.send(cmd).map_err(SerialError::from)
+    }
+}
+
 impl SerialService {
     pub fn list_ports() -> Vec<PortInfo> {
         serialport::available_ports()
@@ -124,100 +377,101 @@ impl SerialService {
             .collect()
     }
 
-    pub fn open(cfg: SerialConfig) -> Result<Self, String> {
+    pub fn open(cfg: SerialConfig) -> Result<Self, SerialError> {
+        Self::open_with(cfg, transport::connect)
+    }
+
+    /// Opens the service on an already established transport. Such a
+    /// service cannot reconnect, since the transport cannot be reopened.
+    pub fn with_transport(cfg: SerialConfig, transport: Box<dyn Transport>) -> Result<Self, SerialError> {
+        let mut transport = Some(transport);
+        Self::open_with(cfg, move |cfg| {
+            transport
+                .take()
+                .ok_or_else(|| SerialError::NotFound(cfg.port_name.clone()))
+        })
+    }
+
+    /// Runs the worker on whatever transport `connect` produces; `connect` is
+    /// called on the worker thread so slow opens do not block the caller, and
+    /// again for every reconnect attempt.
+    pub fn open_with<F>(cfg: SerialConfig, connect: F) -> Result<Self, SerialError>
+    where
+        F: FnMut(&SerialConfig) -> Result<Box<dyn Transport>, SerialError> + Send + 'static,
+    {
         let (tx_cmd, rx_cmd) = unbounded::<Command>();
         let (tx_evt, rx_evt) = unbounded::<SerialEvent>();
-        let cfg_clone = cfg.clone();
-
-        std::thread::spawn(move || {
-            match serialport::new(&cfg_clone.port_name, cfg_clone.baud_rate)
-                .data_bits(cfg_clone.data_bits)
-                .parity(cfg_clone.parity)
-                .stop_bits(cfg_clone.stop_bits)
-                .flow_control(cfg_clone.flow_control)
-                .timeout(Duration::from_millis(50))
-                .open()
-            {
-                Ok(mut port) => {
-                    let _ = tx_evt.send(SerialEvent::Opened(cfg_clone.port_name.clone()));
-                    let mut buf = [0u8; 4096];
-                    loop {
-                        match port.read(&mut buf) {
-                            Ok(n) if n > 0 => {
-                                let _ = tx_evt.send(SerialEvent::Rx(buf[..n].to_vec()));
-                            }
-                            Ok(_) | Err(_) => {}
-                        }
-                        while let Ok(cmd) = rx_cmd.try_recv() {
-                            match cmd {
-                                Command::Send(data) => {
-                                    match port.write(&data) {
-                                        Ok(n) => { let _ = tx_evt.send(SerialEvent::Tx(n)); }
-                                        Err(e) => { let _ = tx_evt.send(SerialEvent::Error(e.to_string())); }
-                                    }
-                                }
-                                Command::SetDtr(state) => {
-                                    if let Err(e) = port.write_data_terminal_ready(state) {
-                                        let _ = tx_evt.send(SerialEvent::Error(e.to_string()));
-                                    }
-                                }
-                                Command::SetRts(state) => {
-                                    if let Err(e) = port.write_request_to_send(state) {
-                                        let _ = tx_evt.send(SerialEvent::Error(e.to_string()));
-                                    }
-                                }
-                                Command::GetPinStates => {
-                                    let states = PinStates {
-                                        cts: port.read_clear_to_send().unwrap_or(false),
-                                        dsr: port.read_data_set_ready().unwrap_or(false),
-                                        dcd: port.read_carrier_detect().unwrap_or(false),
-                                        ri: port.read_ring_indicator().unwrap_or(false),
-                                    };
-                                    let _ = tx_evt.send(SerialEvent::PinStates(states));
-                                }
-                                Command::Close => {
-                                    let _ = tx_evt.send(SerialEvent::Closed);
-                                    return;
-                                }
-                            }
-                        }
-                        std::thread::sleep(Duration::from_millis(5));
-                    }
-                }
-                Err(e) => {
-                    let _ = tx_evt.send(SerialEvent::Error(format!("open failed: {e}")));
-                    let _ = tx_evt.send(SerialEvent::Closed);
-                }
-            }
-        });
-
-        Ok(Self { cfg, tx_cmd, rx_evt })
-    }
-
-    pub fn send(&self, data: Vec<u8>) -> Result<(), String> {
-        self.tx_cmd.send(Command::Send(data)).map_err(|e| e.to_string())
-    }
-
-    pub fn set_dtr(&self, state: bool) -> Result<(), String> {
-        self.tx_cmd.send(Command::SetDtr(state)).map_err(|e| e.to_string())
-    }
-
-    pub fn set_rts(&self, state: bool) -> Result<(), String> {
-        self.tx_cmd.send(Command::SetRts(state)).map_err(|e| e.to_string())
-    }
-
-    pub fn request_pin_states(&self) -> Result<(), String> {
-        self.tx_cmd.send(Command::GetPinStates).map_err(|e| e.to_string())
+        let subscribers = Subscribers::default();
+        let events = EventSink { main: tx_evt, subscribers: subscribers.clone() };
+        let config = Arc::new(Mutex::new(cfg));
+        let worker_config = config.clone();
+
+        std::thread::spawn(move || worker::run_worker(worker_config, connect, rx_cmd, events));
+
+        Ok(Self {
+            handle: SerialHandle { tx_cmd, subscribers, config },
+            rx_evt,
+        })
+    }
+
+    pub fn send(&self, data: Vec<u8>) -> Result<(), SerialError> {
+        self.handle.send(data)
+    }
+
+    pub fn cancel_tx(&self) -> Result<(), SerialError> {
+        self.handle.cancel_tx()
+    }
+
+    pub fn set_dtr(&self, state: bool) -> Result<(), SerialError> {
+        self.handle.set_dtr(state)
+    }
+
+    pub fn set_rts(&self, state: bool) -> Result<(), SerialError> {
+        self.handle.set_rts(state)
+    }
+
+    pub fn request_pin_states(&self) -> Result<(), SerialError> {
+        self.handle.request_pin_states()
+    }
+
+    pub fn reconfigure(&self, cfg: SerialConfig) -> Result<(), SerialError> {
+        self.handle.reconfigure(cfg)
+    }
+
+    pub fn set_pacing(&self, pacing: Option<TxPacing>) -> Result<(), SerialError> {
+        self.handle.set_pacing(pacing)
+    }
+
+    pub fn set_break(&self, state: bool) -> Result<(), SerialError> {
+        self.handle.set_break(state)
+    }
+
+    pub fn send_break(&self, duration: Duration) -> Result<(), SerialError> {
+        self.handle.send_break(duration)
+    }
+
+    pub fn run_pin_sequence(&self, sequence: PinSequence) -> Result<(), SerialError> {
+        self.handle.run_pin_sequence(sequence)
+    }
+
+    pub fn handle(&self) -> SerialHandle {
+        self.handle.clone()
+    }
+
+    /// A new receiver that gets a copy of every event emitted from now on.
+    pub fn subscribe(&self) -> Receiver<SerialEvent> {
+        self.handle.subscribe()
     }
 
     pub fn close(&self) {
-        let _ = self.tx_cmd.send(Command::Close);
+        let _ = self.handle.tx_cmd.send(Command::Close);
     }
 
     pub fn events(&self) -> &Receiver<SerialEvent> {
         &self.rx_evt
     }
 
-    pub fn config(&self) -> &SerialConfig { &self.cfg }
+    pub fn config(&self) -> SerialConfig {
+        self.handle.config()
+    }
 }
-
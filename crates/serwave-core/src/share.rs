@@ -0,0 +1,492 @@
+//! Shares an open `SerialService` with network clients, ser2net style.
+//!
+//! RX from the port is fanned out to every client; data from clients with
+//! write permission is merged into the port's TX.
+
+use crate::rfc2217::{
+    self, TelnetEvent, TelnetParser, BINARY, COM_PORT_OPTION, CONTROL_BREAK_OFF, CONTROL_BREAK_ON,
+    CONTROL_DTR_OFF, CONTROL_DTR_ON, CONTROL_RTS_OFF, CONTROL_RTS_ON, DO, DONT, IAC,
+    NOTIFY_MODEMSTATE, SERVER_OFFSET, SET_BAUDRATE, SET_CONTROL, SET_DATASIZE, SET_PARITY, SET_STOPSIZE, SGA, WILL, WONT,
+};
+use crate::serial_service::{PinStates, SerialConfig, SerialEvent, SerialHandle, SerialService};
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender, TrySendError};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use serialport::DataBits;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Chunks a client may have waiting; one that falls further behind is dropped
+/// so it cannot hold up RX for everyone else.
+const CLIENT_QUEUE_LEN: usize = 256;
+/// Longest a single write to a client may block its writer thread.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShareProtocol {
+    Raw,
+    Rfc2217,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    ReadOnly,
+    ReadWrite,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShareConfig {
+    pub bind_addr: String,
+    pub protocol: ShareProtocol,
+    /// Permission given to newly connected clients.
+    pub default_permission: Permission,
+}
+
+impl Default for ShareConfig {
+    fn default() -> Self {
+        Self {
+            bind_addr: "0.0.0.0:7000".to_string(),
+            protocol: ShareProtocol::Raw,
+            default_permission: Permission::ReadOnly,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub peer: SocketAddr,
+    pub permission: Permission,
+}
+
+#[derive(Debug, Clone)]
+pub enum ShareEvent {
+    Connected(ClientInfo),
+    Disconnected(u64),
+    /// Data a client wrote to the port.
+    Tx { client: u64, data: Vec<u8> },
+    /// Data from a read-only client that was dropped.
+    Denied { client: u64, len: usize },
+}
+
+struct Client {
+    peer: SocketAddr,
+    writable: AtomicBool,
+    /// Only used to shut the connection down; reads and writes have their own handles.
+    stream: TcpStream,
+    /// Drained by the client's writer thread.
+    outbox: Sender<Vec<u8>>,
+}
+
+impl Client {
+    fn permission(&self) -> Permission {
+        if self.writable.load(Ordering::Relaxed) {
+            Permission::ReadWrite
+        } else {
+            Permission::ReadOnly
+        }
+    }
+
+    /// Queues `data` for the client without blocking; fails once it has fallen behind.
+    fn send(&self, data: &[u8]) -> io::Result<()> {
+        self.outbox.try_send(data.to_vec()).map_err(|e| match e {
+            TrySendError::Full(_) => io::Error::new(io::ErrorKind::WouldBlock, "client fell behind"),
+            TrySendError::Disconnected(_) => io::ErrorKind::BrokenPipe.into(),
+        })
+    }
+
+    fn close(&self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+struct Shared {
+    protocol: ShareProtocol,
+    default_permission: Permission,
+    handle: SerialHandle,
+    clients: Mutex<HashMap<u64, Arc<Client>>>,
+    next_id: AtomicU64,
+    modem_state: AtomicU8,
+    dtr: AtomicBool,
+    rts: AtomicBool,
+    stop: AtomicBool,
+    events: Sender<ShareEvent>,
+}
+
+pub struct ShareServer {
+    shared: Arc<Shared>,
+    local_addr: SocketAddr,
+    rx_events: Receiver<ShareEvent>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ShareServer {
+    pub fn start(service: &SerialService, cfg: ShareConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind(&cfg.bind_addr)?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+
+        let (tx_events, rx_events) = unbounded();
+        let shared = Arc::new(Shared {
+            protocol: cfg.protocol,
+            default_permission: cfg.default_permission,
+            handle: service.handle(),
+            clients: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            modem_state: AtomicU8::new(0),
+            dtr: AtomicBool::new(false),
+            rts: AtomicBool::new(false),
+            stop: AtomicBool::new(false),
+            events: tx_events,
+        });
+
+        let serial_events = service.subscribe();
+        let threads = vec![
+            {
+                let shared = shared.clone();
+                std::thread::spawn(move || accept_loop(shared, listener))
+            },
+            {
+                let shared = shared.clone();
+                std::thread::spawn(move || fan_out_loop(shared, serial_events))
+            },
+        ];
+
+        Ok(Self { shared, local_addr, rx_events, threads })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn protocol(&self) -> ShareProtocol {
+        self.shared.protocol
+    }
+
+    pub fn clients(&self) -> Vec<ClientInfo> {
+        let mut clients: Vec<ClientInfo> = self
+            .shared
+            .clients
+            .lock()
+            .iter()
+            .map(|(&id, c)| ClientInfo { id, peer: c.peer, permission: c.permission() })
+            .collect();
+        clients.sort_by_key(|c| c.id);
+        clients
+    }
+
+    pub fn set_permission(&self, client: u64, permission: Permission) {
+        if let Some(c) = self.shared.clients.lock().get(&client) {
+            c.writable.store(permission == Permission::ReadWrite, Ordering::Relaxed);
+        }
+    }
+
+    pub fn disconnect(&self, client: u64) {
+        if let Some(c) = self.shared.clients.lock().remove(&client) {
+            c.close();
+        }
+    }
+
+    pub fn events(&self) -> &Receiver<ShareEvent> {
+        &self.rx_events
+    }
+
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+        for t in self.threads.drain(..) {
+            let _ = t.join();
+        }
+        for (_, c) in self.shared.clients.lock().drain() {
+            c.close();
+        }
+    }
+}
+
+impl Drop for ShareServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn accept_loop(shared: Arc<Shared>, listener: TcpListener) {
+    while !shared.stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                if let Err(e) = add_client(&shared, stream, peer) {
+                    log::warn!("share: failed to set up client {peer}: {e}");
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => {
+                log::warn!("share: accept failed: {e}");
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+fn add_client(shared: &Arc<Shared>, stream: TcpStream, peer: SocketAddr) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let reader = stream.try_clone()?;
+    let writer = stream.try_clone()?;
+
+    let id = shared.next_id.fetch_add(1, Ordering::Relaxed);
+    let (outbox, queued) = bounded(CLIENT_QUEUE_LEN);
+    let client = Arc::new(Client {
+        peer,
+        writable: AtomicBool::new(shared.default_permission == Permission::ReadWrite),
+        stream,
+        outbox,
+    });
+    std::thread::spawn(move || writer_loop(writer, queued));
+    if shared.protocol == ShareProtocol::Rfc2217 {
+        client.send(&[
+            IAC, WILL, BINARY,
+            IAC, DO, BINARY,
+            IAC, WILL, SGA,
+            IAC, DO, SGA,
+            IAC, DO, COM_PORT_OPTION,
+        ])?;
+    }
+    shared.clients.lock().insert(id, client.clone());
+    let _ = shared.events.send(ShareEvent::Connected(ClientInfo {
+        id,
+        peer,
+        permission: client.permission(),
+    }));
+
+    let shared = shared.clone();
+    std::thread::spawn(move || {
+        client_loop(&shared, id, &client, reader);
+        shared.clients.lock().remove(&id);
+        let _ = shared.events.send(ShareEvent::Disconnected(id));
+    });
+    Ok(())
+}
+
+/// Writes queued data to the client until the queue's sender is dropped with
+/// the client; a failed or timed-out write hangs the connection up.
+fn writer_loop(mut stream: TcpStream, queued: Receiver<Vec<u8>>) {
+    for data in queued {
+        if stream.write_all(&data).is_err() {
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+    }
+}
+
+fn client_loop(shared: &Shared, id: u64, client: &Client, mut reader: TcpStream) {
+    let mut parser = TelnetParser::default();
+    let mut buf = [0u8; 4096];
+    // Line settings asked for since the client last went quiet or sent data
+    let mut pending = None;
+    while !shared.stop.load(Ordering::Relaxed) {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                apply_settings(shared, &mut pending);
+                continue;
+            }
+            Err(_) => break,
+        };
+
+        let data = match shared.protocol {
+            ShareProtocol::Raw => buf[..n].to_vec(),
+            ShareProtocol::Rfc2217 => {
+                let mut data = Vec::new();
+                let mut reply = Vec::new();
+                let mut events = Vec::new();
+                parser.feed(&buf[..n], |ev| events.push(ev));
+                for ev in events {
+                    match ev {
+                        TelnetEvent::Data(b) => data.push(b),
+                        TelnetEvent::Negotiate(cmd, opt) => {
+                            let supported = matches!(opt, BINARY | SGA | COM_PORT_OPTION);
+                            match cmd {
+                                DO if !supported => reply.extend([IAC, WONT, opt]),
+                                WILL if !supported => reply.extend([IAC, DONT, opt]),
+                                _ => {}
+                            }
+                        }
+                        TelnetEvent::Sub(sub) => reply.extend(com_port_reply(shared, client, &sub, &mut pending)),
+                    }
+                }
+                if !reply.is_empty() && client.send(&reply).is_err() {
+                    break;
+                }
+                data
+            }
+        };
+
+        if data.is_empty() {
+            continue;
+        }
+        // Data goes out with the settings the client asked for before it
+        apply_settings(shared, &mut pending);
+        if client.writable.load(Ordering::Relaxed) {
+            let _ = shared.handle.send(data.clone());
+            let _ = shared.events.send(ShareEvent::Tx { client: id, data });
+        } else {
+            let _ = shared.events.send(ShareEvent::Denied { client: id, len: data.len() });
+        }
+    }
+    apply_settings(shared, &mut pending);
+}
+
+/// Reconfigures the port once for a burst of line setting requests.
+fn apply_settings(shared: &Shared, pending: &mut Option<SerialConfig>) {
+    if let Some(cfg) = pending.take() {
+        let _ = shared.handle.reconfigure(cfg);
+    }
+}
+
+/// Answers one RFC 2217 COM-port subnegotiation.
+///
+/// Line setting changes are collected in `pending` and DTR/RTS/BREAK requests
+/// applied for clients with write permission; everyone else, and queries, get
+/// the current values.
+fn com_port_reply(shared: &Shared, client: &Client, sub: &[u8], pending: &mut Option<SerialConfig>) -> Vec<u8> {
+    let [COM_PORT_OPTION, cmd, value @ ..] = sub else {
+        return Vec::new();
+    };
+    let writable = client.writable.load(Ordering::Relaxed);
+    let mut cfg = pending.clone().unwrap_or_else(|| shared.handle.config());
+    if writable {
+        if let Some(requested) = requested_settings(&cfg, *cmd, value) {
+            *pending = Some(requested.clone());
+            cfg = requested;
+        }
+    }
+    let answer = match *cmd {
+        SET_BAUDRATE => cfg.baud_rate.to_be_bytes().to_vec(),
+        SET_DATASIZE => vec![rfc2217::data_bits_code(cfg.data_bits)],
+        SET_PARITY => vec![rfc2217::parity_code(cfg.parity)],
+        SET_STOPSIZE => vec![rfc2217::stop_bits_code(cfg.stop_bits)],
+        SET_CONTROL => match value.first().copied() {
+            Some(v @ (CONTROL_DTR_ON | CONTROL_DTR_OFF)) => {
+                if writable {
+                    let on = v == CONTROL_DTR_ON;
+                    shared.dtr.store(on, Ordering::Relaxed);
+                    let _ = shared.handle.set_dtr(on);
+                }
+                vec![if shared.dtr.load(Ordering::Relaxed) { CONTROL_DTR_ON } else { CONTROL_DTR_OFF }]
+            }
+            Some(v @ (CONTROL_BREAK_ON | CONTROL_BREAK_OFF)) => {
+                if writable {
+                    let _ = shared.handle.set_break(v == CONTROL_BREAK_ON);
+                }
+                vec![v]
+            }
+            Some(v @ (CONTROL_RTS_ON | CONTROL_RTS_OFF)) => {
+                if writable {
+                    let on = v == CONTROL_RTS_ON;
+                    shared.rts.store(on, Ordering::Relaxed);
+                    let _ = shared.handle.set_rts(on);
+                }
+                vec![if shared.rts.load(Ordering::Relaxed) { CONTROL_RTS_ON } else { CONTROL_RTS_OFF }]
+            }
+            _ => vec![rfc2217::flow_control_code(cfg.flow_control)],
+        },
+        NOTIFY_MODEMSTATE => vec![shared.modem_state.load(Ordering::Relaxed)],
+        _ => value.to_vec(),
+    };
+    rfc2217::subnegotiation(cmd + SERVER_OFFSET, &answer)
+}
+
+/// `cfg` with the line setting a client asked for, if it asked for a change.
+fn requested_settings(cfg: &SerialConfig, cmd: u8, value: &[u8]) -> Option<SerialConfig> {
+    let mut cfg = cfg.clone();
+    match (cmd, value) {
+        (SET_BAUDRATE, &[a, b, c, d]) => {
+            let baud_rate = u32::from_be_bytes([a, b, c, d]);
+            if baud_rate == 0 {
+                return None;
+            }
+            cfg.baud_rate = baud_rate;
+        }
+        (SET_DATASIZE, &[code, ..]) => cfg.data_bits = DataBits::try_from(code).ok()?,
+        (SET_PARITY, &[code, ..]) => cfg.parity = rfc2217::parity_from_code(code)?,
+        (SET_STOPSIZE, &[code, ..]) => cfg.stop_bits = rfc2217::stop_bits_from_code(code)?,
+        (SET_CONTROL, &[code, ..]) => cfg.flow_control = rfc2217::flow_control_from_code(code)?,
+        _ => return None,
+    }
+    Some(cfg)
+}
+
+fn fan_out_loop(shared: Arc<Shared>, serial_events: Receiver<SerialEvent>) {
+    while !shared.stop.load(Ordering::Relaxed) {
+        let Ok(event) = serial_events.recv_timeout(POLL_INTERVAL) else {
+            continue;
+        };
+        match event {
+            SerialEvent::Rx { data, .. } => {
+                let data = match shared.protocol {
+                    ShareProtocol::Raw => data,
+                    ShareProtocol::Rfc2217 => rfc2217::escape(&data),
+                };
+                broadcast(&shared, &data);
+            }
+            SerialEvent::PinStates(states) => publish_modem_state(&shared, &states),
+            SerialEvent::PinChanged { line, state, .. } => {
+                let mut states = rfc2217::modem_to_pins(shared.modem_state.load(Ordering::Relaxed));
+                states.set(line, state);
+                publish_modem_state(&shared, &states);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn publish_modem_state(shared: &Shared, states: &PinStates) {
+    let state = modem_byte(states);
+    let previous = shared.modem_state.swap(state, Ordering::Relaxed);
+    if previous != state && shared.protocol == ShareProtocol::Rfc2217 {
+        broadcast(shared, &rfc2217::subnegotiation(NOTIFY_MODEMSTATE + SERVER_OFFSET, &[state]));
+    }
+}
+
+fn broadcast(shared: &Shared, data: &[u8]) {
+    let clients: Vec<(u64, Arc<Client>)> = shared
+        .clients
+        .lock()
+        .iter()
+        .map(|(&id, c)| (id, c.clone()))
+        .collect();
+    for (id, client) in clients {
+        if let Err(e) = client.send(data) {
+            log::warn!("share: dropping client {}: {e}", client.peer);
+            client.close();
+            shared.clients.lock().remove(&id);
+        }
+    }
+}
+
+fn modem_byte(states: &PinStates) -> u8 {
+    let mut state = 0;
+    if states.cts {
+        state |= rfc2217::MODEM_CTS;
+    }
+    if states.dsr {
+        state |= rfc2217::MODEM_DSR;
+    }
+    if states.ri {
+        state |= rfc2217::MODEM_RI;
+    }
+    if states.dcd {
+        state |= rfc2217::MODEM_CD;
+    }
+    state
+}
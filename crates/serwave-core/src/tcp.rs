@@ -0,0 +1,207 @@
+//! Raw TCP transports, for boards behind ser2net / ESP-Link style bridges.
+
+use crate::transport::{Transport, TransportReader};
+use parking_lot::Mutex;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+fn resolve(addr: &str) -> io::Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+    if addrs.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("cannot resolve {addr}")));
+    }
+    Ok(addrs)
+}
+
+fn prepare(stream: &TcpStream) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))
+}
+
+/// Connects out to a remote raw TCP serial bridge.
+pub struct TcpClientTransport {
+    stream: TcpStream,
+}
+
+/// Connects to `addr` with the read timeout transports expect.
+pub(crate) fn connect_stream(addr: &str) -> io::Result<TcpStream> {
+    let mut last_err = None;
+    for sa in resolve(addr)? {
+        match TcpStream::connect_timeout(&sa, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                prepare(&stream)?;
+                return Ok(stream);
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, addr.to_string())))
+}
+
+impl TcpClientTransport {
+    pub fn connect(addr: &str) -> io::Result<Self> {
+        Ok(Self { stream: connect_stream(addr)? })
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+}
+
+fn read_stream(stream: &mut TcpStream, buf: &mut [u8]) -> io::Result<usize> {
+    match stream.read(buf)? {
+        0 => Err(io::Error::new(io::ErrorKind::ConnectionAborted, "connection closed by peer")),
+        n => Ok(n),
+    }
+}
+
+struct TcpReader {
+    stream: TcpStream,
+}
+
+impl TransportReader for TcpReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        read_stream(&mut self.stream, buf)
+    }
+}
+
+impl Transport for TcpClientTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        read_stream(&mut self.stream, buf)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.stream.write_all(data)?;
+        Ok(data.len())
+    }
+
+    fn split_reader(&mut self) -> Option<Box<dyn TransportReader>> {
+        let stream = self.stream.try_clone().ok()?;
+        Some(Box::new(TcpReader { stream }))
+    }
+
+    fn close(&mut self) {
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+    }
+}
+
+/// Listens on a local address and serves one client at a time.
+///
+/// Until a client connects reads return nothing and writes fail with
+/// `NotConnected`. When the client leaves, the next one is accepted.
+pub struct TcpServerTransport {
+    server: Arc<Server>,
+    /// Reads on the calling thread until it is handed to a reader thread.
+    reader: ServerReader,
+}
+
+/// The listener and the client being served, shared with the reading side.
+struct Server {
+    listener: TcpListener,
+    /// Written to by the transport; only the reader accepts and drops clients.
+    client: Mutex<Option<TcpStream>>,
+}
+
+impl TcpServerTransport {
+    pub fn bind(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(resolve(addr)?.as_slice())?;
+        listener.set_nonblocking(true)?;
+        let server = Arc::new(Server { listener, client: Mutex::new(None) });
+        Ok(Self { reader: ServerReader { server: server.clone(), stream: None }, server })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.server.listener.local_addr()
+    }
+}
+
+/// Accepts clients and reads from the current one.
+struct ServerReader {
+    server: Arc<Server>,
+    /// Our own handle on the client, so reads never wait on writes.
+    stream: Option<TcpStream>,
+}
+
+impl ServerReader {
+    fn accept_pending(&mut self) -> io::Result<()> {
+        loop {
+            match self.server.listener.accept() {
+                Ok((stream, peer)) => {
+                    if self.stream.is_some() {
+                        log::info!("rejecting {peer}: a client is already connected");
+                        continue;
+                    }
+                    stream.set_nonblocking(false)?;
+                    prepare(&stream)?;
+                    log::info!("accepted TCP client {peer}");
+                    *self.server.client.lock() = Some(stream.try_clone()?);
+                    self.stream = Some(stream);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn drop_client(&mut self) {
+        self.stream = None;
+        if let Some(client) = self.server.client.lock().take() {
+            let _ = client.shutdown(std::net::Shutdown::Both);
+        }
+    }
+}
+
+impl TransportReader for ServerReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.accept_pending()?;
+        let Some(stream) = self.stream.as_mut() else {
+            std::thread::sleep(POLL_INTERVAL);
+            return Ok(0);
+        };
+        match stream.read(buf) {
+            Ok(0) => {
+                log::info!("TCP client disconnected");
+                self.drop_client();
+                Ok(0)
+            }
+            Ok(n) => Ok(n),
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => Ok(0),
+            Err(e) => {
+                log::info!("TCP client dropped: {e}");
+                self.drop_client();
+                Ok(0)
+            }
+        }
+    }
+}
+
+impl Transport for TcpServerTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut client = self.server.client.lock();
+        let client = client
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "no TCP client connected"))?;
+        client.write_all(data)?;
+        Ok(data.len())
+    }
+
+    fn split_reader(&mut self) -> Option<Box<dyn TransportReader>> {
+        let idle = ServerReader { server: self.server.clone(), stream: None };
+        Some(Box::new(std::mem::replace(&mut self.reader, idle)))
+    }
+
+    fn close(&mut self) {
+        if let Some(client) = self.server.client.lock().take() {
+            let _ = client.shutdown(std::net::Shutdown::Both);
+        }
+    }
+}
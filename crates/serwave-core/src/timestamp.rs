@@ -0,0 +1,32 @@
+//! Capture times for received data and line changes.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// When something happened, on both clocks.
+///
+/// `mono` is for measuring the time between events and is not affected by
+/// wall clock adjustments; `wall` is for display and export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    pub mono: Instant,
+    pub wall: SystemTime,
+}
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Self {
+            mono: Instant::now(),
+            wall: SystemTime::now(),
+        }
+    }
+
+    /// Microseconds since the Unix epoch.
+    pub fn wall_micros(&self) -> u64 {
+        self.wall.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64
+    }
+
+    /// Time from `earlier` to `self` on the monotonic clock, zero if `earlier` is later.
+    pub fn since(&self, earlier: &Timestamp) -> Duration {
+        self.mono.saturating_duration_since(earlier.mono)
+    }
+}
@@ -0,0 +1,199 @@
+//! Request/response exchanges: send a command, wait for the reply.
+
+use crate::error::SerialError;
+use crate::serial_service::{Command, SerialEvent, SerialHandle, SerialService, Written};
+use crate::timestamp::Timestamp;
+use crossbeam_channel::{bounded, never, select, Receiver};
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// Accepts the bytes received so far as a complete response.
+pub type ResponsePredicate = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// How the end of a response is recognised in the received bytes.
+#[derive(Clone)]
+pub enum ResponseMatcher {
+    /// Everything up to and including the first occurrence of the sequence.
+    Terminator(Vec<u8>),
+    /// The first `n` bytes.
+    ByteCount(usize),
+    /// The first match; bytes before it are unsolicited.
+    Regex(regex::bytes::Regex),
+    /// Everything received so far, once the predicate accepts it.
+    Predicate(ResponsePredicate),
+}
+
+impl ResponseMatcher {
+    pub fn terminator(terminator: impl Into<Vec<u8>>) -> Self {
+        Self::Terminator(terminator.into())
+    }
+
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self::Regex(regex::bytes::Regex::new(pattern)?))
+    }
+
+    pub fn predicate(f: impl Fn(&[u8]) -> bool + Send + Sync + 'static) -> Self {
+        Self::Predicate(Arc::new(f))
+    }
+
+    /// Where the complete response sits in `received`, if it is there yet.
+    fn find(&self, received: &[u8]) -> Option<Range<usize>> {
+        match self {
+            Self::Terminator(terminator) if terminator.is_empty() => None,
+            Self::Terminator(terminator) => received
+                .windows(terminator.len())
+                .position(|w| w == terminator.as_slice())
+                .map(|pos| 0..pos + terminator.len()),
+            Self::ByteCount(n) => (received.len() >= *n).then_some(0..*n),
+            Self::Regex(re) => re.find(received).map(|m| m.range()),
+            Self::Predicate(accept) => accept(received).then_some(0..received.len()),
+        }
+    }
+}
+
+impl fmt::Debug for ResponseMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Terminator(t) => f.debug_tuple("Terminator").field(&String::from_utf8_lossy(t)).finish(),
+            Self::ByteCount(n) => f.debug_tuple("ByteCount").field(n).finish(),
+            Self::Regex(re) => f.debug_tuple("Regex").field(&re.as_str()).finish(),
+            Self::Predicate(_) => f.write_str("Predicate(..)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    pub request: Vec<u8>,
+    pub matcher: ResponseMatcher,
+    /// Counted from the call, so it includes the time spent writing.
+    pub timeout: Duration,
+}
+
+impl Transaction {
+    pub fn new(request: impl Into<Vec<u8>>, matcher: ResponseMatcher) -> Self {
+        Self {
+            request: request.into(),
+            matcher,
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TransactionResult {
+    pub response: Vec<u8>,
+    /// From the last request byte being written to the arrival of the chunk
+    /// that completed the response.
+    pub latency: Duration,
+    /// Bytes received during the exchange that are not part of the
+    /// response, e.g. log output before it or the start of the next message.
+    pub unsolicited: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum TransactionError {
+    #[error("no matching response within {timeout:?}")]
+    Timeout { timeout: Duration, received: Vec<u8> },
+    #[error(transparent)]
+    Serial(#[from] SerialError),
+}
+
+/// Gathers RX until the matcher finds the response.
+pub(crate) struct Collector<'a> {
+    matcher: &'a ResponseMatcher,
+    received: Vec<u8>,
+}
+
+impl<'a> Collector<'a> {
+    pub fn new(matcher: &'a ResponseMatcher) -> Self {
+        Self { matcher, received: Vec::new() }
+    }
+
+    /// Adds a chunk; once the response is complete, returns it along with
+    /// everything else received.
+    pub fn push(&mut self, data: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.received.extend_from_slice(data);
+        let range = self.matcher.find(&self.received)?;
+        let response = self.received[range.clone()].to_vec();
+        let mut unsolicited = self.received[..range.start].to_vec();
+        unsolicited.extend_from_slice(&self.received[range.end..]);
+        Some((response, unsolicited))
+    }
+
+    pub fn into_received(self) -> Vec<u8> {
+        self.received
+    }
+}
+
+/// Turns the reply of a `Send` into the time the request was written.
+pub(crate) fn write_started(result: Result<Written, SerialError>) -> Result<Timestamp, SerialError> {
+    result.map(|written| written.at)
+}
+
+/// The error for an event that ends the exchange early, if it is one.
+pub(crate) fn interrupted(event: &SerialEvent) -> Option<SerialError> {
+    match event {
+        SerialEvent::Closed => Some(SerialError::ChannelClosed),
+        SerialEvent::Disconnected(e) => Some(e.clone()),
+        _ => None,
+    }
+}
+
+impl SerialHandle {
+    /// Sends `transaction.request` and blocks until the response matches or
+    /// the timeout passes. Other subscribers still see all events.
+    pub fn transact(&self, transaction: &Transaction) -> Result<TransactionResult, TransactionError> {
+        let deadline = Instant::now() + transaction.timeout;
+        // Subscribed before sending, so a fast reply is not missed
+        let events = self.subscribe();
+        let (tx_done, rx_done) = bounded(1);
+        let reply = Box::new(move |result| {
+            let _ = tx_done.send(write_started(result));
+        });
+        self.command(Command::Send(transaction.request.clone(), Some(reply)))?;
+
+        let mut collector = Collector::new(&transaction.matcher);
+        let mut done: Receiver<_> = rx_done;
+        let mut written = None;
+        loop {
+            select! {
+                recv(done) -> result => {
+                    written = Some(result.unwrap_or(Err(SerialError::ChannelClosed))?);
+                    done = never();
+                }
+                recv(events) -> event => {
+                    let event = event.map_err(|_| SerialError::ChannelClosed)?;
+                    if let Some(e) = interrupted(&event) {
+                        return Err(e.into());
+                    }
+                    let SerialEvent::Rx { data, at } = event else { continue };
+                    if let Some((response, unsolicited)) = collector.push(&data) {
+                        let written = match written {
+                            Some(written) => written,
+                            // The reply can be queued behind the data that answered it
+                            None => done.recv().unwrap_or(Err(SerialError::ChannelClosed))?,
+                        };
+                        let latency = at.since(&written);
+                        return Ok(TransactionResult { response, latency, unsolicited });
+                    }
+                }
+                default(deadline.saturating_duration_since(Instant::now())) => {
+                    return Err(TransactionError::Timeout {
+                        timeout: transaction.timeout,
+                        received: collector.into_received(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl SerialService {
+    pub fn transact(&self, transaction: &Transaction) -> Result<TransactionResult, TransactionError> {
+        self.handle().transact(transaction)
+    }
+}
@@ -0,0 +1,200 @@
+//! Byte transports that can drive the `SerialService` worker.
+
+use crate::error::{classify_serialport, SerialError};
+use crate::serial_service::{PinStates, SerialConfig};
+use crate::rfc2217::Rfc2217Transport;
+use crate::tcp::{TcpClientTransport, TcpServerTransport};
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+/// A bidirectional byte stream with optional modem control lines.
+///
+/// `read` should return `Ok(0)` or a `TimedOut`/`WouldBlock` error when no
+/// data arrived within a short poll interval, so the worker can service
+/// commands in between.
+pub trait Transport: Send {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize>;
+
+    fn set_dtr(&mut self, _state: bool) -> io::Result<()> {
+        Err(unsupported("DTR"))
+    }
+
+    fn set_rts(&mut self, _state: bool) -> io::Result<()> {
+        Err(unsupported("RTS"))
+    }
+
+    /// Starts (`true`) or ends a BREAK condition on the TX line.
+    fn set_break(&mut self, _state: bool) -> io::Result<()> {
+        Err(unsupported("BREAK"))
+    }
+
+    fn pin_states(&mut self) -> io::Result<PinStates> {
+        Ok(PinStates { cts: false, dsr: false, dcd: false, ri: false })
+    }
+
+    /// Modem input lines for the worker's periodic sampling, which runs
+    /// between sends and so must not wait on the device. Transports that have
+    /// to ask a remote end should answer from what they already know.
+    fn sample_pin_states(&mut self) -> io::Result<PinStates> {
+        self.pin_states()
+    }
+
+    /// Applies baud rate, data bits, parity, stop bits and flow control from
+    /// `cfg` to the open connection.
+    fn reconfigure(&mut self, _cfg: &SerialConfig) -> io::Result<()> {
+        Err(unsupported("changing line settings"))
+    }
+
+    /// A second handle that reads from the same connection, so a dedicated
+    /// thread can block in `read` while writes and line control go through
+    /// `self`. `None` when the connection cannot be shared; the worker then
+    /// alternates between reads and commands on one thread.
+    fn split_reader(&mut self) -> Option<Box<dyn TransportReader>> {
+        None
+    }
+
+    fn close(&mut self) {}
+}
+
+/// The receiving half handed out by [`Transport::split_reader`].
+///
+/// Like `Transport::read`, it should return within a short poll interval
+/// when no data arrives so the reader thread notices the port closing.
+pub trait TransportReader: Send {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+pub(crate) fn unsupported(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, format!("{what} is not supported by this transport"))
+}
+
+/// Transport backed by a local serial device through the `serialport` crate.
+pub struct SerialPortTransport {
+    port: Box<dyn serialport::SerialPort>,
+}
+
+impl SerialPortTransport {
+    pub fn open(cfg: &SerialConfig) -> Result<Self, SerialError> {
+        let port = serialport::new(&cfg.port_name, cfg.baud_rate)
+            .data_bits(cfg.data_bits)
+            .parity(cfg.parity)
+            .stop_bits(cfg.stop_bits)
+            .flow_control(cfg.flow_control)
+            .timeout(Duration::from_millis(50))
+            .open()
+            .map_err(|e| classify_serialport(e, &cfg.port_name))?;
+        Ok(Self { port })
+    }
+}
+
+impl Transport for SerialPortTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.port.read(buf)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.port.write(data)
+    }
+
+    fn set_dtr(&mut self, state: bool) -> io::Result<()> {
+        Ok(self.port.write_data_terminal_ready(state)?)
+    }
+
+    fn set_rts(&mut self, state: bool) -> io::Result<()> {
+        Ok(self.port.write_request_to_send(state)?)
+    }
+
+    fn set_break(&mut self, state: bool) -> io::Result<()> {
+        if state {
+            Ok(self.port.set_break()?)
+        } else {
+            Ok(self.port.clear_break()?)
+        }
+    }
+
+    fn pin_states(&mut self) -> io::Result<PinStates> {
+        Ok(PinStates {
+            cts: self.port.read_clear_to_send().unwrap_or(false),
+            dsr: self.port.read_data_set_ready().unwrap_or(false),
+            dcd: self.port.read_carrier_detect().unwrap_or(false),
+            ri: self.port.read_ring_indicator().unwrap_or(false),
+        })
+    }
+
+    fn reconfigure(&mut self, cfg: &SerialConfig) -> io::Result<()> {
+        self.port.set_baud_rate(cfg.baud_rate)?;
+        self.port.set_data_bits(cfg.data_bits)?;
+        self.port.set_parity(cfg.parity)?;
+        self.port.set_stop_bits(cfg.stop_bits)?;
+        self.port.set_flow_control(cfg.flow_control)?;
+        Ok(())
+    }
+
+    fn split_reader(&mut self) -> Option<Box<dyn TransportReader>> {
+        match self.port.try_clone() {
+            Ok(port) => Some(Box::new(SerialPortReader { port })),
+            Err(e) => {
+                log::debug!("cannot clone serial port, reading on the control thread: {e}");
+                None
+            }
+        }
+    }
+}
+
+struct SerialPortReader {
+    port: Box<dyn serialport::SerialPort>,
+}
+
+impl TransportReader for SerialPortReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.port.read(buf)
+    }
+}
+
+/// Where a `SerialConfig::port_name` points to.
+///
+/// Plain names are local serial devices; URL-style names select other
+/// transports: `loop://`, `tcp://host:port`, `tcp-listen://addr:port` and
+/// `rfc2217://host:port`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Endpoint {
+    Serial(String),
+    Loopback,
+    TcpClient(String),
+    TcpServer(String),
+    Rfc2217(String),
+}
+
+impl Endpoint {
+    pub fn parse(name: &str) -> Self {
+        if name == "loop://" {
+            Self::Loopback
+        } else if let Some(addr) = name.strip_prefix("tcp://") {
+            Self::TcpClient(addr.to_string())
+        } else if let Some(addr) = name.strip_prefix("tcp-listen://") {
+            Self::TcpServer(addr.to_string())
+        } else if let Some(addr) = name.strip_prefix("rfc2217://") {
+            Self::Rfc2217(addr.to_string())
+        } else {
+            Self::Serial(name.to_string())
+        }
+    }
+
+    pub fn is_network(&self) -> bool {
+        matches!(self, Self::TcpClient(_) | Self::TcpServer(_) | Self::Rfc2217(_))
+    }
+}
+
+/// Opens the transport described by `cfg`.
+pub fn connect(cfg: &SerialConfig) -> Result<Box<dyn Transport>, SerialError> {
+    let open_failed = |e| SerialError::open_failed(e, &cfg.port_name);
+    Ok(match cfg.endpoint() {
+        Endpoint::Serial(_) => Box::new(SerialPortTransport::open(cfg)?),
+        Endpoint::Loopback => Box::new(crate::mock::MockPort::loopback().transport()),
+        Endpoint::TcpClient(addr) => Box::new(TcpClientTransport::connect(&addr).map_err(open_failed)?),
+        Endpoint::TcpServer(addr) => Box::new(TcpServerTransport::bind(&addr).map_err(open_failed)?),
+        Endpoint::Rfc2217(addr) => Box::new(Rfc2217Transport::connect(&addr, cfg).map_err(open_failed)?),
+    })
+}
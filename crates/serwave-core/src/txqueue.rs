@@ -0,0 +1,138 @@
+//! Paced transmission for devices that drop characters when data arrives
+//! faster than they can handle it.
+
+use crate::error::SerialError;
+use crate::serial_service::{answer, Reply, Written};
+use crate::timestamp::Timestamp;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Delays inserted between written bytes and lines.
+///
+/// The delays are kept exactly on transports with a split reader (serial
+/// ports, TCP, RFC 2217, PTYs, the mock); on custom transports without one
+/// they may stretch by up to the transport's read poll interval.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TxPacing {
+    /// Pause after every byte; zero writes each line in one go.
+    pub inter_byte: Duration,
+    /// Pause after the last byte of each line ending; never shorter than `inter_byte`.
+    pub inter_line: Duration,
+}
+
+impl TxPacing {
+    pub fn is_active(&self) -> bool {
+        !self.inter_byte.is_zero() || !self.inter_line.is_zero()
+    }
+
+    /// How much of `data` goes out in the next write.
+    fn chunk_len(&self, data: &[u8], line_end: u8) -> usize {
+        if !self.inter_byte.is_zero() {
+            data.len().min(1)
+        } else if !self.inter_line.is_zero() {
+            data.iter().position(|&b| b == line_end).map_or(data.len(), |pos| pos + 1)
+        } else {
+            data.len()
+        }
+    }
+
+    fn delay_after(&self, written: &[u8], line_end: u8) -> Duration {
+        if written.last() == Some(&line_end) {
+            self.inter_line.max(self.inter_byte)
+        } else {
+            self.inter_byte
+        }
+    }
+}
+
+struct PendingSend {
+    data: Vec<u8>,
+    sent: usize,
+    reply: Option<Reply<Written>>,
+}
+
+/// Progress after one paced write.
+pub(crate) struct TxStep {
+    pub sent: usize,
+    pub total: usize,
+    pub queued: usize,
+    /// The caller to tell once `sent == total`.
+    pub reply: Option<Reply<Written>>,
+}
+
+/// Sends waiting to be written under a `TxPacing`.
+#[derive(Default)]
+pub(crate) struct TxQueue {
+    sends: VecDeque<PendingSend>,
+    next_write: Option<Instant>,
+}
+
+impl TxQueue {
+    pub fn push(&mut self, data: Vec<u8>, reply: Option<Reply<Written>>) {
+        if data.is_empty() {
+            answer(reply, Ok(Written { len: 0, at: Timestamp::now() }));
+        } else {
+            self.sends.push_back(PendingSend { data, sent: 0, reply });
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sends.is_empty()
+    }
+
+    /// Bytes not written yet, over all queued sends.
+    pub fn queued(&self) -> usize {
+        self.sends.iter().map(|s| s.data.len() - s.sent).sum()
+    }
+
+    /// Drops everything not written yet and returns how many bytes that was.
+    pub fn clear(&mut self) -> usize {
+        let dropped = self.queued();
+        for send in self.sends.drain(..) {
+            answer(send.reply, Err(SerialError::Cancelled));
+        }
+        dropped
+    }
+
+    /// Like `clear` after a failed write: the send being written gets `error`,
+    /// the ones behind it are cancelled.
+    pub fn fail(&mut self, error: SerialError) -> usize {
+        if let Some(send) = self.sends.front_mut() {
+            answer(send.reply.take(), Err(error));
+        }
+        self.clear()
+    }
+
+    /// When the next write is due, if anything is queued.
+    pub fn due(&self) -> Option<Instant> {
+        if self.sends.is_empty() {
+            return None;
+        }
+        Some(self.next_write.unwrap_or_else(Instant::now))
+    }
+
+    /// The bytes of the next write under `pacing`.
+    pub fn next_chunk(&self, pacing: &TxPacing, line_end: u8) -> &[u8] {
+        match self.sends.front() {
+            Some(send) => {
+                let rest = &send.data[send.sent..];
+                &rest[..pacing.chunk_len(rest, line_end)]
+            }
+            None => &[],
+        }
+    }
+
+    /// Records that `n` bytes of the next chunk were written and schedules
+    /// the following write.
+    pub fn advance(&mut self, n: usize, pacing: &TxPacing, line_end: u8) -> TxStep {
+        let Some(send) = self.sends.front_mut() else {
+            return TxStep { sent: 0, total: 0, queued: 0, reply: None };
+        };
+        let written = &send.data[send.sent..send.sent + n];
+        self.next_write = Some(Instant::now() + pacing.delay_after(written, line_end));
+        send.sent += n;
+        let (sent, total) = (send.sent, send.data.len());
+        let reply = if sent == total { self.sends.pop_front().and_then(|s| s.reply) } else { None };
+        TxStep { sent, total, queued: self.queued(), reply }
+    }
+}
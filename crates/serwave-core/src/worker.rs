@@ -0,0 +1,479 @@
+//! The thread(s) behind a `SerialService`.
+//!
+//! Transports that can hand out a separate reader get a dedicated thread
+//! blocking in `read`, while the control thread sleeps on the command channel
+//! and writes as soon as a command arrives. Transports that cannot be split
+//! are driven by a single thread alternating between reads and commands.
+
+use crate::error::SerialError;
+use crate::pinseq::{PinSequence, PinStep};
+use crate::serial_service::{
+    answer, Command, EventSink, PinStates, PortIdentity, ReconnectPolicy, Reply, SerialConfig, SerialEvent, SerialService, Written,
+};
+use crate::timestamp::Timestamp;
+use crate::transport::{Endpoint, Transport, TransportReader};
+use crate::txqueue::{TxQueue, TxStep};
+use crossbeam_channel::{bounded, select, Receiver, TryRecvError};
+use parking_lot::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often the modem input lines are sampled while idle. Changes are found
+/// by comparing samples, so a pulse shorter than this can go unnoticed. Ports
+/// served by `serve_single` are sampled once per read timeout instead.
+const PIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+fn is_transient(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock | std::io::ErrorKind::Interrupted
+    )
+}
+
+enum Exit {
+    Closed,
+    Lost(SerialError),
+}
+
+pub(crate) fn run_worker<F>(config: Arc<Mutex<SerialConfig>>, mut connect: F, rx_cmd: Receiver<Command>, events: EventSink)
+where
+    F: FnMut(&SerialConfig) -> Result<Box<dyn Transport>, SerialError>,
+{
+    let cfg = config.lock().clone();
+    let identity = match cfg.endpoint() {
+        Endpoint::Serial(name) => SerialService::list_ports()
+            .into_iter()
+            .find(|p| p.port_name == name)
+            .and_then(|p| p.identity()),
+        _ => None,
+    };
+
+    let mut port = match connect(&cfg) {
+        Ok(port) => port,
+        Err(e) => {
+            events.emit(SerialEvent::Error(e));
+            events.emit(SerialEvent::Closed);
+            return;
+        }
+    };
+    // Sampled before announcing the port, so changes right after `Opened` are seen
+    let mut pins = port.pin_states().ok();
+    events.emit(SerialEvent::Opened(cfg.port_name.clone()));
+
+    loop {
+        let exit = serve(port.as_mut(), pins, &config, &rx_cmd, &events);
+        port.close();
+        match exit {
+            Exit::Closed => break,
+            Exit::Lost(e) => {
+                events.emit(SerialEvent::Disconnected(e));
+                let Some(policy) = cfg.reconnect else { break };
+                match reconnect(&config, &mut connect, identity.as_ref(), policy, &rx_cmd, &events) {
+                    Some(p) => {
+                        port = p;
+                        pins = port.pin_states().ok();
+                        events.emit(SerialEvent::Reconnected(config.lock().port_name.clone()));
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+    events.emit(SerialEvent::Closed);
+}
+
+/// Pumps data and commands until the port is closed or lost.
+fn serve(
+    port: &mut dyn Transport,
+    pins: Option<PinStates>,
+    config: &Mutex<SerialConfig>,
+    rx_cmd: &Receiver<Command>,
+    events: &EventSink,
+) -> Exit {
+    let reader = port.split_reader();
+    let mut control = Control {
+        port,
+        pins,
+        config,
+        events,
+        in_break: false,
+        break_until: None,
+        tx: TxQueue::default(),
+    };
+    match reader {
+        Some(reader) => serve_split(&mut control, reader, rx_cmd),
+        None => serve_single(&mut control, rx_cmd),
+    }
+}
+
+/// Reader thread plus this thread handling commands the moment they arrive.
+fn serve_split(control: &mut Control, reader: Box<dyn TransportReader>, rx_cmd: &Receiver<Command>) -> Exit {
+    let stop = Arc::new(AtomicBool::new(false));
+    let (tx_exit, rx_exit) = bounded(1);
+    let reader_thread = {
+        let stop = stop.clone();
+        let events = control.events.clone();
+        std::thread::spawn(move || {
+            let _ = tx_exit.send(read_loop(reader, &events, &stop));
+        })
+    };
+
+    let exit = loop {
+        control.tick();
+        select! {
+            recv(rx_cmd) -> cmd => match cmd {
+                Ok(cmd) => {
+                    if !control.handle(cmd) {
+                        break Exit::Closed;
+                    }
+                }
+                // Every handle is gone, nobody can close us any more
+                Err(_) => break Exit::Closed,
+            },
+            recv(rx_exit) -> exit => break exit.unwrap_or(Exit::Closed),
+            default(control.idle_timeout()) => {}
+        }
+    };
+
+    // Before stopping the reader, which some transports need for acknowledgements
+    control.finish();
+    stop.store(true, Ordering::Relaxed);
+    let _ = reader_thread.join();
+    exit
+}
+
+fn read_loop(mut reader: Box<dyn TransportReader>, events: &EventSink, stop: &AtomicBool) -> Exit {
+    let mut buf = vec![0u8; READ_BUFFER_SIZE];
+    while !stop.load(Ordering::Relaxed) {
+        match reader.read(&mut buf) {
+            Ok(n) if n > 0 => {
+                let at = Timestamp::now();
+                events.emit(SerialEvent::Rx { data: buf[..n].to_vec(), at });
+            }
+            Ok(_) => {}
+            Err(e) if is_transient(&e) => {}
+            Err(e) => return read_failed(e, events),
+        }
+    }
+    Exit::Closed
+}
+
+/// One thread alternating between a short read and the queued commands.
+fn serve_single(control: &mut Control, rx_cmd: &Receiver<Command>) -> Exit {
+    let mut buf = vec![0u8; READ_BUFFER_SIZE];
+    loop {
+        control.tick();
+        match control.port.read(&mut buf) {
+            Ok(n) if n > 0 => {
+                let at = Timestamp::now();
+                control.events.emit(SerialEvent::Rx { data: buf[..n].to_vec(), at });
+            }
+            Ok(_) => {}
+            Err(e) if is_transient(&e) => {}
+            Err(e) => {
+                let exit = read_failed(e, control.events);
+                control.finish();
+                return exit;
+            }
+        }
+        loop {
+            match rx_cmd.try_recv() {
+                Ok(cmd) => {
+                    if !control.handle(cmd) {
+                        control.finish();
+                        return Exit::Closed;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    control.finish();
+                    return Exit::Closed;
+                }
+            }
+        }
+    }
+}
+
+fn read_failed(e: std::io::Error, events: &EventSink) -> Exit {
+    let e = SerialError::from_io(e);
+    if e.is_disconnect() {
+        return Exit::Lost(e);
+    }
+    events.emit(SerialEvent::Error(e));
+    Exit::Closed
+}
+
+/// The writing/control side of an open port.
+struct Control<'a> {
+    port: &'a mut dyn Transport,
+    pins: Option<PinStates>,
+    config: &'a Mutex<SerialConfig>,
+    events: &'a EventSink,
+    /// A BREAK is on, held or timed
+    in_break: bool,
+    /// End of a timed BREAK started by `SendBreak`
+    break_until: Option<Instant>,
+    /// Paced sends not fully written yet
+    tx: TxQueue,
+}
+
+impl Control<'_> {
+    /// Housekeeping between commands: ends timed breaks, writes paced data
+    /// that is due and reports modem lines that changed since the last sample.
+    fn tick(&mut self) {
+        if self.break_until.is_some_and(|t| Instant::now() >= t) {
+            self.break_until = None;
+            self.set_break(false);
+        }
+        self.pump_tx();
+        if let (Some(old), Ok(new)) = (self.pins, self.port.sample_pin_states()) {
+            let at = Timestamp::now();
+            for line in new.changed_lines(&old) {
+                self.events.emit(SerialEvent::PinChanged { line, state: new.get(line), at });
+            }
+            self.pins = Some(new);
+        }
+    }
+
+    /// How long to wait for a command before the next `tick` is due.
+    fn idle_timeout(&self) -> Duration {
+        let now = Instant::now();
+        [self.break_until, self.tx.due()]
+            .into_iter()
+            .flatten()
+            .map(|t| t.saturating_duration_since(now))
+            .fold(PIN_POLL_INTERVAL, Duration::min)
+    }
+
+    /// Writes queued data as far as the pacing allows right now.
+    fn pump_tx(&mut self) {
+        let (pacing, line_end) = {
+            let cfg = self.config.lock();
+            (cfg.pacing.unwrap_or_default(), cfg.line_ending.as_bytes().last().copied().unwrap_or(b'\n'))
+        };
+        while self.tx.due().is_some_and(|t| Instant::now() >= t) {
+            let chunk = self.tx.next_chunk(&pacing, line_end);
+            let at = Timestamp::now();
+            match self.port.write(chunk) {
+                Ok(n) => {
+                    let TxStep { sent, total, queued, reply } = self.tx.advance(n, &pacing, line_end);
+                    self.events.emit(SerialEvent::TxProgress { sent, total, queued });
+                    if sent == total {
+                        self.events.emit(SerialEvent::Tx(total));
+                        answer(reply, Ok(Written { len: total, at }));
+                    }
+                }
+                Err(e) => {
+                    let e = SerialError::from_io(e);
+                    self.events.emit(SerialEvent::Error(e.clone()));
+                    let dropped = self.tx.fail(e);
+                    self.events.emit(SerialEvent::TxCancelled { dropped });
+                }
+            }
+        }
+    }
+
+    /// Executes one command; `false` once the port should be closed.
+    fn handle(&mut self, cmd: Command) -> bool {
+        let port = &mut *self.port;
+        let events = self.events;
+        match cmd {
+            Command::Send(data, reply) => {
+                let paced = self.config.lock().pacing.is_some_and(|p| p.is_active());
+                // Behind a paced send even when pacing was just switched off, to keep the order
+                if paced || !self.tx.is_empty() {
+                    self.tx.push(data, reply);
+                    self.pump_tx();
+                    return true;
+                }
+                let at = Timestamp::now();
+                let result = report(events, port.write(&data));
+                if let Ok(n) = result {
+                    events.emit(SerialEvent::Tx(n));
+                }
+                answer(reply, result.map(|len| Written { len, at }));
+            }
+            Command::CancelTx => {
+                let dropped = self.tx.clear();
+                events.emit(SerialEvent::TxCancelled { dropped });
+            }
+            Command::SetDtr(state, reply) => answer(reply, report(events, port.set_dtr(state))),
+            Command::SetRts(state, reply) => answer(reply, report(events, port.set_rts(state))),
+            Command::GetPinStates(reply) => {
+                let result = report(events, port.pin_states());
+                if let Ok(states) = result {
+                    events.emit(SerialEvent::PinStates(states));
+                }
+                answer(reply, result);
+            }
+            Command::Reconfigure(new) => {
+                let current = self.config.lock().clone();
+                let new = line_settings(&current, new);
+                // The line ending only matters to senders, so the port is left alone for it
+                let result = if same_line_settings(&current, &new) { Ok(()) } else { port.reconfigure(&new) };
+                match result {
+                    Ok(()) => {
+                        *self.config.lock() = new.clone();
+                        events.emit(SerialEvent::Reconfigured(new));
+                    }
+                    Err(e) => { events.emit(SerialEvent::Error(SerialError::from_io(e))); }
+                }
+            }
+            Command::SetPacing(pacing) => self.config.lock().pacing = pacing,
+            Command::SetBreak(state) => {
+                self.break_until = None;
+                self.set_break(state);
+            }
+            Command::SendBreak(duration) => {
+                if self.set_break(true) {
+                    self.break_until = Some(Instant::now() + duration);
+                }
+            }
+            Command::RunPinSequence(sequence) => {
+                match run_pin_sequence(port, &sequence) {
+                    Ok(()) => { events.emit(SerialEvent::PinSequenceDone(sequence.name)); }
+                    Err(e) => { events.emit(SerialEvent::Error(SerialError::from_io(e))); }
+                }
+            }
+            Command::Close => return false,
+        }
+        true
+    }
+
+    fn set_break(&mut self, state: bool) -> bool {
+        match self.port.set_break(state) {
+            Ok(()) => {
+                self.in_break = state;
+                self.events.emit(SerialEvent::Break(state));
+                true
+            }
+            Err(e) => {
+                self.events.emit(SerialEvent::Error(SerialError::from_io(e)));
+                false
+            }
+        }
+    }
+
+    /// Releases a BREAK still on when the port closes, whether held or timed.
+    fn finish(&mut self) {
+        self.break_until = None;
+        if self.in_break {
+            self.set_break(false);
+        }
+    }
+}
+
+/// Emits an `Error` event for a failed operation and hands the result on for a reply.
+fn report<T>(events: &EventSink, result: std::io::Result<T>) -> Result<T, SerialError> {
+    result.map_err(|e| {
+        let e = SerialError::from_io(e);
+        events.emit(SerialEvent::Error(e.clone()));
+        e
+    })
+}
+
+fn run_pin_sequence(port: &mut dyn Transport, sequence: &PinSequence) -> std::io::Result<()> {
+    for step in &sequence.steps {
+        match *step {
+            PinStep::Dtr(state) => port.set_dtr(state)?,
+            PinStep::Rts(state) => port.set_rts(state)?,
+            PinStep::Delay(delay) => std::thread::sleep(delay),
+        }
+    }
+    Ok(())
+}
+
+/// Retries `connect` with backoff until it succeeds, the policy gives up or
+/// the service is closed. USB devices are looked up by identity, so a device
+/// that comes back under a different name is still found.
+fn reconnect<F>(
+    config: &Mutex<SerialConfig>,
+    connect: &mut F,
+    identity: Option<&PortIdentity>,
+    policy: ReconnectPolicy,
+    rx_cmd: &Receiver<Command>,
+    events: &EventSink,
+) -> Option<Box<dyn Transport>>
+where
+    F: FnMut(&SerialConfig) -> Result<Box<dyn Transport>, SerialError>,
+{
+    let mut delay = policy.initial_delay;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        if policy.max_attempts.is_some_and(|max| attempt > max) {
+            return None;
+        }
+        events.emit(SerialEvent::Reconnecting { attempt, delay });
+
+        let deadline = Instant::now() + delay;
+        while let Ok(cmd) = rx_cmd.recv_deadline(deadline) {
+            match cmd {
+                Command::Close => return None,
+                Command::CancelTx => {}
+                Command::GetPinStates(reply) => answer(reply, Err(reconnecting())),
+                // Nothing to apply to yet; the next open picks the settings up
+                Command::Reconfigure(new) => {
+                    let new = line_settings(&config.lock(), new);
+                    *config.lock() = new.clone();
+                    events.emit(SerialEvent::Reconfigured(new));
+                }
+                Command::SetPacing(pacing) => config.lock().pacing = pacing,
+                Command::Send(_, reply) => reject(events, reply),
+                Command::SetDtr(_, reply) | Command::SetRts(_, reply) => reject(events, reply),
+                _ => events.emit(SerialEvent::Error(reconnecting())),
+            }
+        }
+
+        let mut cfg = config.lock().clone();
+        if let Some(identity) = identity {
+            match SerialService::list_ports().into_iter().find(|p| identity.matches(p)) {
+                Some(p) => cfg.port_name = p.port_name,
+                None => {
+                    delay = (delay * policy.multiplier).min(policy.max_delay);
+                    continue;
+                }
+            }
+        }
+        match connect(&cfg) {
+            Ok(port) => {
+                config.lock().port_name = cfg.port_name;
+                return Some(port);
+            }
+            Err(e) => log::debug!("reconnect attempt {attempt} failed: {e}"),
+        }
+        delay = (delay * policy.multiplier).min(policy.max_delay);
+    }
+}
+
+fn reconnecting() -> SerialError {
+    SerialError::Disconnected(Arc::new(std::io::Error::new(
+        std::io::ErrorKind::NotConnected,
+        "port is reconnecting",
+    )))
+}
+
+/// Refuses a command while there is no port to run it on.
+fn reject<T>(events: &EventSink, reply: Option<Reply<T>>) {
+    events.emit(SerialEvent::Error(reconnecting()));
+    answer(reply, Err(reconnecting()));
+}
+
+/// `requested` line settings on top of the identity of `current`.
+fn line_settings(current: &SerialConfig, requested: SerialConfig) -> SerialConfig {
+    SerialConfig {
+        port_name: current.port_name.clone(),
+        reconnect: current.reconnect,
+        ..requested
+    }
+}
+
+fn same_line_settings(a: &SerialConfig, b: &SerialConfig) -> bool {
+    a.baud_rate == b.baud_rate
+        && a.data_bits == b.data_bits
+        && a.parity == b.parity
+        && a.stop_bits == b.stop_bits
+        && a.flow_control == b.flow_control
+}
@@ -0,0 +1,121 @@
+#![cfg(feature = "tokio")]
+
+use serwave_core::{
+    AsyncSerialService, MockPort, ResponseMatcher, SerialConfig, SerialError, SerialEvent, Transaction, Transport, TxPacing,
+};
+use std::io;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+async fn open_mock(port: &MockPort, cfg: SerialConfig) -> AsyncSerialService {
+    AsyncSerialService::with_transport(cfg, Box::new(port.transport())).await.unwrap()
+}
+
+#[tokio::test]
+async fn send_resolves_after_write_and_rx_arrives_as_stream() {
+    let port = MockPort::loopback();
+    let mut service = open_mock(&port, SerialConfig::default()).await;
+    let mut events = service.events();
+
+    assert_eq!(service.send(b"hi".to_vec()).await.unwrap(), 2);
+    assert_eq!(port.written(), b"hi");
+
+    let rx = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            if let Some(SerialEvent::Rx { data, .. }) = events.next().await {
+                return data;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(rx, b"hi");
+}
+
+#[tokio::test]
+async fn pin_control_is_applied_before_resolving() {
+    let port = MockPort::new();
+    let service = open_mock(&port, SerialConfig::default()).await;
+
+    service.set_dtr(true).await.unwrap();
+    assert!(port.dtr());
+    // Default wiring loops DTR back to DSR
+    assert!(service.pin_states().await.unwrap().dsr);
+}
+
+#[tokio::test]
+async fn cancelled_paced_send_reports_cancelled() {
+    let port = MockPort::new();
+    let cfg = SerialConfig {
+        pacing: Some(TxPacing { inter_byte: Duration::from_millis(200), inter_line: Duration::ZERO }),
+        ..Default::default()
+    };
+    let service = open_mock(&port, cfg).await;
+
+    let send = service.send(b"slow".to_vec());
+    let cancel = async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        service.cancel_tx().unwrap();
+    };
+    let (result, ()) = tokio::join!(send, cancel);
+    assert!(matches!(result, Err(SerialError::Cancelled)));
+    assert_eq!(port.written(), b"s");
+}
+
+/// Takes the first byte written, then fails every write.
+#[derive(Default)]
+struct FailsAfterOneByte {
+    written: usize,
+}
+
+impl Transport for FailsAfterOneByte {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        std::thread::sleep(Duration::from_millis(5));
+        Ok(0)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.written > 0 {
+            return Err(io::Error::other("write failed"));
+        }
+        self.written = data.len();
+        Ok(data.len())
+    }
+}
+
+#[tokio::test]
+async fn failed_paced_write_reports_its_error_and_cancels_the_rest() {
+    let cfg = SerialConfig {
+        pacing: Some(TxPacing { inter_byte: Duration::from_millis(20), inter_line: Duration::ZERO }),
+        ..Default::default()
+    };
+    let service = AsyncSerialService::with_transport(cfg, Box::<FailsAfterOneByte>::default()).await.unwrap();
+
+    let (first, second) = tokio::join!(service.send(b"ab".to_vec()), service.send(b"cd".to_vec()));
+    assert!(matches!(first, Err(SerialError::Io(_))), "{first:?}");
+    assert!(matches!(second, Err(SerialError::Cancelled)), "{second:?}");
+}
+
+#[tokio::test]
+async fn transact_waits_for_matching_line() {
+    let port = MockPort::loopback();
+    let service = open_mock(&port, SerialConfig::default()).await;
+
+    // The loopback echoes the request, which the regex skips over
+    let transaction = Transaction::new("ping\n", ResponseMatcher::regex("pi.g").unwrap());
+    let result = service.transact(&transaction).await.unwrap();
+    assert_eq!(result.response, b"ping");
+    assert_eq!(result.unsolicited, b"\n");
+    // Timed from the write even though the echo can arrive before its reply
+    assert!(result.latency > Duration::ZERO, "{:?}", result.latency);
+}
+
+#[tokio::test]
+async fn open_reports_missing_device() {
+    let cfg = SerialConfig {
+        port_name: "/dev/serwave-does-not-exist".into(),
+        ..Default::default()
+    };
+    assert!(matches!(AsyncSerialService::open(cfg).await, Err(SerialError::NotFound(_))));
+}
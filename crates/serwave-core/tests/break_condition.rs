@@ -0,0 +1,22 @@
+//! BREAK conditions driven through the worker, checked on a mock port.
+
+use serwave_core::{MockPort, SerialConfig, SerialEvent};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+#[test]
+fn closing_releases_a_held_break() {
+    let port = MockPort::new();
+    let service = port.open(SerialConfig::default()).unwrap();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Opened(_)));
+
+    service.set_break(true).unwrap();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Break(true)));
+    assert!(port.in_break());
+
+    service.close();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Break(false)));
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Closed));
+    assert!(!port.in_break());
+}
@@ -0,0 +1,138 @@
+use serwave_core::{Expect, ExpectError, MockPort, Script, ScriptProgress, SerialConfig, SerialEvent, SerialService};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn open() -> (MockPort, SerialService) {
+    let port = MockPort::new();
+    let service = port.open(SerialConfig::default()).unwrap();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Opened(_)));
+    (port, service)
+}
+
+/// A fake Linux console answering each line written to it.
+fn console(port: &MockPort, done: Arc<AtomicBool>) -> JoinHandle<()> {
+    let port = port.clone();
+    std::thread::spawn(move || {
+        let mut pending = Vec::new();
+        while !done.load(Ordering::Relaxed) {
+            pending.extend(port.take_written());
+            while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=pos).collect();
+                let reply: &[u8] = match line.as_slice() {
+                    b"\n" => b"\r\nbuildroot login: ",
+                    b"root\n" => b"Password: ",
+                    b"secret\n" => b"\r\n# ",
+                    b"ip -4 addr show eth0\n" => b"    inet 10.0.0.7/24 brd 10.0.0.255\r\n# ",
+                    _ => b"sh: not found\r\n# ",
+                };
+                port.inject_rx(reply);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    })
+}
+
+const LOGIN: &str = r##"
+# Log in and find the address
+timeout 2s
+sendline ""
+expect
+    "login: $" => login
+    "# $" => shell
+end
+login:
+sendline "root"
+expect "Password: $"
+sendline "${password}"
+shell:
+sendline "ip -4 addr show eth0"
+expect "inet (?P<ip>[0-9.]+)"
+log "board is at ${ip}"
+"##;
+
+#[test]
+fn script_logs_in_and_captures_variables() {
+    let (port, service) = open();
+    let done = Arc::new(AtomicBool::new(false));
+    let device = console(&port, done.clone());
+
+    let script = Script::parse(LOGIN).unwrap();
+    let mut expect = Expect::new(service.handle());
+    expect.set_var("password", "secret");
+    let mut messages = Vec::new();
+    let mut matched = Vec::new();
+    script
+        .run(&mut expect, |p| match p {
+            ScriptProgress::Message(m) => messages.push(m),
+            ScriptProgress::Matched { text, .. } => matched.push(text),
+            ScriptProgress::Step { .. } => {}
+        })
+        .unwrap();
+
+    assert_eq!(expect.var("ip"), Some("10.0.0.7"));
+    assert_eq!(messages, ["board is at 10.0.0.7"]);
+    assert_eq!(matched, ["login: ", "Password: ", "inet 10.0.0.7"]);
+    done.store(true, Ordering::Relaxed);
+    device.join().unwrap();
+}
+
+#[test]
+fn timeouts_branch_or_fail_with_line() {
+    let (_port, service) = open();
+    let mut expect = Expect::new(service.handle());
+
+    let script = Script::parse("timeout 100ms\nexpect \"never\"\n").unwrap();
+    match script.run(&mut expect, |_| {}) {
+        Err(ExpectError::AtLine { line: 2, source }) => assert!(matches!(*source, ExpectError::Timeout { .. })),
+        other => panic!("expected timeout at line 2, got {other:?}"),
+    }
+
+    let script = Script::parse("timeout 50ms\nexpect\n  \"never\" => ok\n  timeout => late\nend\nok:\nlate:\nfail \"gave up after ${n} tries\"\n").unwrap();
+    expect.set_var("n", "1");
+    match script.run(&mut expect, |_| {}) {
+        Err(ExpectError::AtLine { line: 8, source }) => assert_eq!(source.to_string(), "gave up after 1 tries"),
+        other => panic!("expected fail at line 8, got {other:?}"),
+    }
+}
+
+#[test]
+fn parse_errors_point_at_the_line() {
+    let err = Script::parse("send \"x\"\ngoto nowhere\n").unwrap_err();
+    assert_eq!((err.line, err.message.as_str()), (2, "unknown label nowhere"));
+    let err = Script::parse("timeout 1s\n\nexpect \"(\"\n").unwrap_err();
+    assert_eq!(err.line, 3);
+    let err = Script::parse("expect\n  \"a\" => x\n").unwrap_err();
+    assert_eq!(err.message, "expect block without end");
+    assert!(Script::parse("bogus 1").is_err());
+}
+
+#[test]
+fn stop_flag_aborts_a_wait() {
+    let (_port, service) = open();
+    let mut expect = Expect::new(service.handle());
+    let stop = expect.stop_flag();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        stop.store(true, Ordering::Relaxed);
+    });
+    assert!(matches!(expect.expect("never"), Err(ExpectError::Stopped)));
+}
+
+#[test]
+fn stop_flag_ends_a_loop_that_never_waits() {
+    let (port, service) = open();
+    let mut expect = Expect::new(service.handle());
+    let stop = expect.stop_flag();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        stop.store(true, Ordering::Relaxed);
+    });
+
+    let script = Script::parse("a:\nsend \"x\"\ngoto a\n").unwrap();
+    assert!(matches!(script.run(&mut expect, |_| {}), Err(ExpectError::Stopped)));
+    assert!(!port.written().is_empty());
+}
@@ -0,0 +1,80 @@
+use serwave_core::{FrameBoundary, Framer, FramerConfig, Timestamp};
+use std::time::Duration;
+
+/// `ms` milliseconds after a fixed origin.
+fn at(origin: Timestamp, ms: u64) -> Timestamp {
+    let offset = Duration::from_millis(ms);
+    Timestamp {
+        mono: origin.mono + offset,
+        wall: origin.wall + offset,
+    }
+}
+
+fn framer(boundary: FrameBoundary) -> Framer {
+    Framer::new(FramerConfig {
+        boundary,
+        idle_gap: None,
+        max_size: None,
+    })
+}
+
+/// Feeds the chunks at one instant and returns the frames as strings.
+fn frames(framer: &mut Framer, chunks: &[&[u8]]) -> Vec<String> {
+    let now = Timestamp::now();
+    chunks
+        .iter()
+        .flat_map(|chunk| framer.push(chunk, now))
+        .map(|f| String::from_utf8_lossy(&f.data).into_owned())
+        .collect()
+}
+
+#[test]
+fn line_terminators_stay_with_their_line() {
+    assert_eq!(frames(&mut framer(FrameBoundary::Lf), &[b"a\nb\r\nc"]), ["a\n", "b\r\n"]);
+    assert_eq!(frames(&mut framer(FrameBoundary::Cr), &[b"a\rb\r\nc\r"]), ["a\r", "b\r", "\nc\r"]);
+    assert_eq!(frames(&mut framer(FrameBoundary::CrLf), &[b"a\rb\r", b"\nc\n"]), ["a\rb\r\n"]);
+}
+
+#[test]
+fn auto_treats_crlf_as_one_terminator_across_chunks() {
+    let mut f = framer(FrameBoundary::Auto);
+    assert_eq!(frames(&mut f, &[b"one\r"]), Vec::<String>::new());
+    assert_eq!(frames(&mut f, &[b"\ntwo\rthree\n\n"]), ["one\r\n", "two\r", "three\n", "\n"]);
+    assert_eq!(f.flush().map(|f| f.data), None);
+}
+
+#[test]
+fn custom_sequences_and_fixed_length() {
+    let mut f = framer(FrameBoundary::Sequence(b"END".to_vec()));
+    assert_eq!(frames(&mut f, &[b"xEN", b"Dy", b"E", b"ND"]), ["xEND", "yEND"]);
+
+    let mut f = framer(FrameBoundary::Length(4));
+    assert_eq!(frames(&mut f, &[b"\x01\x02\x03", b"\x04\x05\x06\x07\x08\x09"]).len(), 2);
+    assert_eq!(f.pending(), [0x09]);
+}
+
+#[test]
+fn idle_gap_and_max_size_end_frames() {
+    let origin = Timestamp::now();
+    let mut f = Framer::new(FramerConfig {
+        boundary: FrameBoundary::Lf,
+        idle_gap: Some(Duration::from_millis(100)),
+        max_size: Some(4),
+    });
+
+    assert!(f.push(b"ab", at(origin, 0)).is_empty());
+    assert!(f.push(b"c", at(origin, 80)).is_empty());
+    // The gap counts from the last arrival, not the first byte
+    let frames = f.push(b"de", at(origin, 250));
+    assert_eq!(frames.len(), 1);
+    assert_eq!((frames[0].data.as_slice(), frames[0].at), (&b"abc"[..], at(origin, 0)));
+
+    let frames = f.push(b"fghij\n", at(origin, 260));
+    let data: Vec<_> = frames.iter().map(|f| f.data.as_slice()).collect();
+    assert_eq!(data, [&b"defg"[..], b"hij\n"]);
+    assert_eq!(frames[1].at, at(origin, 260));
+
+    assert!(f.push(b"k", at(origin, 300)).is_empty());
+    assert_eq!(f.flush_idle(at(origin, 350)), None);
+    assert_eq!(f.flush_idle(at(origin, 401)).map(|f| f.data), Some(b"k".to_vec()));
+}
@@ -0,0 +1,58 @@
+use serwave_core::{PortEvent, PortInfo, PortWatcher};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn usb(name: &str, serial: &str) -> PortInfo {
+    PortInfo {
+        port_name: name.into(),
+        port_type: "USB".into(),
+        vid: Some(0x10c4),
+        pid: Some(0xea60),
+        serial_number: Some(serial.into()),
+        manufacturer: None,
+        product: None,
+    }
+}
+
+fn watch(initial: Vec<PortInfo>) -> (Arc<Mutex<Vec<PortInfo>>>, PortWatcher) {
+    let ports = Arc::new(Mutex::new(initial));
+    let source = ports.clone();
+    let watcher = PortWatcher::with_source(Duration::from_millis(10), move || source.lock().unwrap().clone());
+    (ports, watcher)
+}
+
+#[test]
+fn initial_ports_are_not_reported() {
+    let (_, watcher) = watch(vec![usb("/dev/ttyUSB0", "A")]);
+    assert!(watcher.events().recv_timeout(Duration::from_millis(100)).is_err());
+    assert_eq!(watcher.ports(), [usb("/dev/ttyUSB0", "A")]);
+}
+
+#[test]
+fn reports_added_and_removed_ports() {
+    let (ports, watcher) = watch(vec![usb("/dev/ttyUSB0", "A")]);
+
+    ports.lock().unwrap().push(usb("/dev/ttyUSB1", "B"));
+    assert_eq!(watcher.events().recv_timeout(TIMEOUT).unwrap(), PortEvent::Added(usb("/dev/ttyUSB1", "B")));
+
+    ports.lock().unwrap().remove(0);
+    assert_eq!(watcher.events().recv_timeout(TIMEOUT).unwrap(), PortEvent::Removed(usb("/dev/ttyUSB0", "A")));
+    assert_eq!(watcher.ports(), [usb("/dev/ttyUSB1", "B")]);
+}
+
+#[test]
+fn reused_name_with_new_device_is_remove_then_add() {
+    let (ports, watcher) = watch(vec![usb("/dev/ttyUSB0", "A")]);
+    *ports.lock().unwrap() = vec![usb("/dev/ttyUSB0", "B")];
+    assert_eq!(watcher.events().recv_timeout(TIMEOUT).unwrap(), PortEvent::Removed(usb("/dev/ttyUSB0", "A")));
+    assert_eq!(watcher.events().recv_timeout(TIMEOUT).unwrap(), PortEvent::Added(usb("/dev/ttyUSB0", "B")));
+}
+
+#[test]
+fn stop_ends_the_watcher() {
+    let (_, mut watcher) = watch(Vec::new());
+    watcher.stop();
+    assert!(watcher.events().recv_timeout(Duration::from_millis(50)).is_err());
+}
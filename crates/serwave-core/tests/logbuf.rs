@@ -0,0 +1,125 @@
+use serwave_core::{Direction, Evicted, LogLimits, LogStore, RenderOptions, TextEncoding};
+
+fn store(max_entries: usize, max_bytes: usize) -> LogStore {
+    LogStore::with_limits(LogLimits { max_entries, max_bytes })
+}
+
+fn contents(log: &LogStore) -> Vec<&[u8]> {
+    log.entries().iter().map(|e| e.data.as_slice()).collect()
+}
+
+#[test]
+fn oldest_entries_go_first_by_count_and_by_bytes() {
+    let mut log = store(3, 1024);
+    for line in [&b"a"[..], b"b", b"c", b"d"] {
+        log.push(Direction::Rx, line.to_vec());
+    }
+    assert_eq!(contents(&log), [b"b", b"c", b"d"]);
+    assert_eq!(log.evicted(), Evicted { entries: 1, bytes: 1 });
+
+    let mut log = store(100, 10);
+    log.push(Direction::Rx, b"0123".to_vec());
+    log.push(Direction::Tx, b"4567".to_vec());
+    log.push(Direction::Rx, b"89ab".to_vec());
+    assert_eq!(contents(&log), [&b"4567"[..], b"89ab"]);
+    assert_eq!((log.len(), log.bytes()), (2, 8));
+
+    // A single oversized entry still shows
+    log.push(Direction::Rx, vec![0; 64]);
+    assert_eq!((log.len(), log.bytes()), (1, 64));
+    assert_eq!(log.evicted(), Evicted { entries: 3, bytes: 12 });
+}
+
+#[test]
+fn shrinking_limits_evicts_immediately_and_clear_resets() {
+    let mut log = store(10, 1024);
+    for i in 0..10u8 {
+        log.push(Direction::Rx, vec![i; 10]);
+    }
+    log.set_limits(LogLimits { max_entries: 10, max_bytes: 35 });
+    assert_eq!(log.len(), 3);
+    assert_eq!(log.entries()[0].data, [7; 10]);
+    assert_eq!(log.evicted(), Evicted { entries: 7, bytes: 70 });
+
+    log.clear();
+    assert!(log.is_empty());
+    assert_eq!((log.bytes(), log.evicted()), (0, Evicted::default()));
+}
+
+const PLAIN: RenderOptions = RenderOptions {
+    show_timestamp: false,
+    show_hex: false,
+    encoding: TextEncoding::Utf8,
+};
+
+fn window(log: &LogStore, start: usize, end: usize) -> Vec<String> {
+    log.render_window(start..end, &PLAIN).into_iter().map(|l| l.text).collect()
+}
+
+#[test]
+fn windows_follow_filter_and_eviction() {
+    let mut log = store(4, 1024);
+    log.push(Direction::Rx, b"r1\r\n".to_vec());
+    log.push(Direction::Tx, b"t1".to_vec());
+    log.push(Direction::Rx, b"r2\n".to_vec());
+    assert_eq!(log.visible_len(), 3);
+    assert_eq!(window(&log, 1, 10), ["TX: t1", "RX: r2"]);
+
+    log.set_filter(true, false);
+    assert_eq!(window(&log, 0, 10), ["RX: r1", "RX: r2"]);
+    log.push(Direction::Info, b"note".to_vec());
+    log.push(Direction::Rx, b"r3".to_vec());
+    // r1 was evicted; the window shifts with it
+    assert_eq!(log.visible_len(), 3);
+    assert_eq!(window(&log, 0, 2), ["RX: r2", "-- note"]);
+
+    log.set_filter(true, true);
+    assert_eq!(window(&log, 0, 10), ["TX: t1", "RX: r2", "-- note", "RX: r3"]);
+    let hex = RenderOptions { show_hex: true, ..PLAIN };
+    assert_eq!(log.render_window(0..1, &hex)[0].text, "TX: 74 31 ");
+}
+
+#[test]
+fn generation_changes_with_anything_visible() {
+    let mut log = store(10, 1024);
+    let mut last = log.generation();
+    let mut changed = |log: &LogStore| {
+        let changed = log.generation() != last;
+        last = log.generation();
+        changed
+    };
+
+    log.push(Direction::Rx, b"a".to_vec());
+    assert!(changed(&log));
+    assert!(!changed(&log));
+    log.set_filter(false, true);
+    assert!(changed(&log));
+    log.set_highlight_keywords(vec!["a".into()]);
+    assert!(changed(&log));
+    log.clear();
+    assert!(changed(&log));
+    assert_eq!(log.visible_len(), 0);
+}
+
+#[test]
+fn evicted_rows_count_only_visible_entries() {
+    let mut log = store(2, 1024);
+    log.set_filter(true, false);
+    log.push(Direction::Rx, b"r1".to_vec());
+    log.push(Direction::Tx, b"t1".to_vec());
+    log.push(Direction::Rx, b"r2".to_vec());
+    log.push(Direction::Rx, b"r3".to_vec());
+    // r1 went as a row, t1 was filtered out
+    assert_eq!((log.evicted().entries, log.evicted_rows()), (2, 1));
+    assert_eq!(window(&log, 0, 10), ["RX: r2", "RX: r3"]);
+}
+
+#[test]
+fn blank_entries_show_as_bare_heads_everywhere() {
+    let mut log = store(10, 1024);
+    log.push(Direction::Rx, b"a\n".to_vec());
+    log.push(Direction::Rx, b"\r\n".to_vec());
+    log.push(Direction::Rx, b"b".to_vec());
+    assert_eq!(window(&log, 0, 10), ["RX: a", "RX: ", "RX: b"]);
+    assert_eq!(log.to_text_with_encoding(false, false, TextEncoding::Utf8), "RX: a\nRX: \nRX: b\n");
+}
@@ -0,0 +1,91 @@
+//! The mock port as seen through a `SerialService`.
+
+use serwave_core::{MockPort, PinStates, PinWiring, SerialConfig, SerialEvent, SerialService};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn next_event(service: &SerialService) -> SerialEvent {
+    service
+        .events()
+        .recv_timeout(TIMEOUT)
+        .expect("no event within timeout")
+}
+
+fn open(port: &MockPort) -> SerialService {
+    let service = port.open(SerialConfig::default()).unwrap();
+    assert!(matches!(next_event(&service), SerialEvent::Opened(_)));
+    service
+}
+
+fn collect_rx(service: &SerialService, len: usize) -> Vec<u8> {
+    let deadline = Instant::now() + TIMEOUT;
+    let mut data = Vec::new();
+    while data.len() < len && Instant::now() < deadline {
+        if let SerialEvent::Rx { data: chunk, .. } = next_event(service) {
+            data.extend_from_slice(&chunk);
+        }
+    }
+    data
+}
+
+/// Answer to a `request_pin_states` issued after whatever was queued before it.
+fn pin_states(service: &SerialService) -> PinStates {
+    service.request_pin_states().unwrap();
+    loop {
+        if let SerialEvent::PinStates(states) = next_event(service) {
+            return states;
+        }
+    }
+}
+
+#[test]
+fn injected_bytes_arrive_as_rx() {
+    let port = MockPort::new();
+    let service = open(&port);
+    port.inject_rx(b"hello");
+    assert_eq!(collect_rx(&service, 5), b"hello");
+}
+
+#[test]
+fn take_written_captures_sends() {
+    let port = MockPort::new();
+    let service = open(&port);
+    service.send(b"AT\r".to_vec()).unwrap();
+    assert!(matches!(next_event(&service), SerialEvent::Tx(3)));
+    assert_eq!(port.take_written(), b"AT\r");
+    assert!(port.written().is_empty());
+}
+
+#[test]
+fn default_wiring_loops_rts_and_dtr_back() {
+    let port = MockPort::new();
+    let service = open(&port);
+    let idle = PinStates { cts: false, dsr: false, dcd: false, ri: false };
+    assert_eq!(pin_states(&service), idle);
+
+    service.set_rts(true).unwrap();
+    assert_eq!(pin_states(&service), PinStates { cts: true, ..idle });
+    service.set_dtr(true).unwrap();
+    assert_eq!(pin_states(&service), PinStates { cts: true, dsr: true, dcd: true, ri: false });
+    assert!(port.dtr() && port.rts());
+}
+
+#[test]
+fn custom_wiring_only_drives_the_wired_lines() {
+    let port = MockPort::new();
+    port.set_wiring(PinWiring { rts_to_cts: false, dtr_to_dsr: true, dtr_to_dcd: false });
+    let service = open(&port);
+
+    service.set_rts(true).unwrap();
+    service.set_dtr(true).unwrap();
+    assert_eq!(pin_states(&service), PinStates { cts: false, dsr: true, dcd: false, ri: false });
+}
+
+#[test]
+fn loop_endpoint_echoes_sends() {
+    let service = SerialService::open(SerialConfig { port_name: "loop://".into(), ..Default::default() }).unwrap();
+    assert!(matches!(next_event(&service), SerialEvent::Opened(_)));
+    service.send(b"echo".to_vec()).unwrap();
+    assert_eq!(collect_rx(&service, 4), b"echo");
+}
@@ -0,0 +1,96 @@
+use serwave_core::{MockPort, SerialConfig, SerialEvent, SerialService, TxPacing};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn open_paced(pacing: TxPacing) -> (MockPort, SerialService) {
+    let port = MockPort::new();
+    let cfg = SerialConfig {
+        pacing: Some(pacing),
+        ..Default::default()
+    };
+    let service = port.open(cfg).unwrap();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Opened(_)));
+    (port, service)
+}
+
+#[test]
+fn writes_byte_by_byte_with_longer_pause_after_lines() {
+    let (port, service) = open_paced(TxPacing {
+        inter_byte: Duration::from_millis(10),
+        inter_line: Duration::from_millis(60),
+    });
+    service.send(b"ab\n".to_vec()).unwrap();
+    service.send(b"c".to_vec()).unwrap();
+
+    let mut progress = Vec::new();
+    let mut done = Vec::new();
+    while done.len() < 2 {
+        match service.events().recv_timeout(TIMEOUT).unwrap() {
+            SerialEvent::TxProgress { sent, total, queued } => progress.push((Instant::now(), sent, total, queued)),
+            SerialEvent::Tx(n) => done.push(n),
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+    assert_eq!(done, [3, 1]);
+    let steps: Vec<_> = progress.iter().map(|&(_, sent, total, queued)| (sent, total, queued)).collect();
+    // The first byte goes out before the second send is queued
+    assert_eq!(steps, [(1, 3, 2), (2, 3, 2), (3, 3, 1), (1, 1, 0)]);
+    assert!(progress[1].0 - progress[0].0 >= Duration::from_millis(9));
+    assert!(progress[3].0 - progress[2].0 >= Duration::from_millis(55));
+    assert_eq!(port.written(), b"ab\nc");
+}
+
+#[test]
+fn cancel_drops_what_is_still_queued() {
+    let (port, service) = open_paced(TxPacing {
+        inter_byte: Duration::from_millis(200),
+        inter_line: Duration::ZERO,
+    });
+    service.send(b"0123456789".to_vec()).unwrap();
+    assert!(matches!(
+        service.events().recv_timeout(TIMEOUT).unwrap(),
+        SerialEvent::TxProgress { sent: 1, total: 10, queued: 9 }
+    ));
+
+    service.cancel_tx().unwrap();
+    assert!(matches!(
+        service.events().recv_timeout(TIMEOUT).unwrap(),
+        SerialEvent::TxCancelled { dropped: 9 }
+    ));
+    assert_eq!(port.written(), b"0");
+
+    // Later sends are paced again from an empty queue
+    service.send(b"x".to_vec()).unwrap();
+    assert!(matches!(
+        service.events().recv_timeout(TIMEOUT).unwrap(),
+        SerialEvent::TxProgress { sent: 1, total: 1, queued: 0 }
+    ));
+}
+
+#[test]
+fn set_pacing_switches_pace_without_reconfiguring() {
+    let (port, service) = open_paced(TxPacing {
+        inter_byte: Duration::from_millis(200),
+        inter_line: Duration::ZERO,
+    });
+    service.send(b"abc".to_vec()).unwrap();
+    assert!(matches!(
+        service.events().recv_timeout(TIMEOUT).unwrap(),
+        SerialEvent::TxProgress { sent: 1, total: 3, queued: 2 }
+    ));
+
+    // After the pause under way the rest goes out at once (paced it would take 400 ms)
+    service.set_pacing(None).unwrap();
+    let started = Instant::now();
+    loop {
+        match service.events().recv_timeout(TIMEOUT).unwrap() {
+            SerialEvent::TxProgress { .. } => {}
+            SerialEvent::Tx(n) => break assert_eq!(n, 3),
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+    assert!(started.elapsed() < Duration::from_millis(350));
+    assert_eq!(port.written(), b"abc");
+    assert_eq!(service.config().pacing, None);
+}
@@ -0,0 +1,33 @@
+//! Modem input line changes reported by the worker, driven through a mock port.
+
+use serwave_core::{MockPort, ModemLine, SerialConfig, SerialEvent, SerialService};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn next_pin_change(service: &SerialService) -> (ModemLine, bool) {
+    loop {
+        match service.events().recv_timeout(TIMEOUT).expect("no pin change") {
+            SerialEvent::PinChanged { line, state, .. } => return (line, state),
+            SerialEvent::Tx(_) | SerialEvent::Rx { .. } => {}
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn reports_each_modem_line_change() {
+    let port = MockPort::new();
+    let service = port.open(SerialConfig::default()).unwrap();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Opened(_)));
+
+    port.set_ring(true);
+    assert_eq!(next_pin_change(&service), (ModemLine::Ri, true));
+    port.set_ring(false);
+    assert_eq!(next_pin_change(&service), (ModemLine::Ri, false));
+
+    // Loopback wiring: DTR drives DSR and DCD
+    service.set_dtr(true).unwrap();
+    assert_eq!(next_pin_change(&service), (ModemLine::Dsr, true));
+    assert_eq!(next_pin_change(&service), (ModemLine::Dcd, true));
+}
@@ -0,0 +1,76 @@
+use serwave_core::{PinSequence, PinStep, SerialConfig, SerialEvent, SerialService, Transport};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+type Change = (Instant, &'static str, bool);
+
+/// Records every line change with the time it happened.
+#[derive(Clone, Default)]
+struct Recorder {
+    changes: Arc<Mutex<Vec<Change>>>,
+}
+
+impl Transport for Recorder {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        std::thread::sleep(Duration::from_millis(5));
+        Ok(0)
+    }
+
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        Ok(data.len())
+    }
+
+    fn set_dtr(&mut self, state: bool) -> io::Result<()> {
+        self.changes.lock().unwrap().push((Instant::now(), "DTR", state));
+        Ok(())
+    }
+
+    fn set_rts(&mut self, state: bool) -> io::Result<()> {
+        self.changes.lock().unwrap().push((Instant::now(), "RTS", state));
+        Ok(())
+    }
+}
+
+#[test]
+fn text_form_round_trips() {
+    let seq = PinSequence::esp32_classic_reset();
+    let text = seq.to_string();
+    assert_eq!(text, "DTR=0 RTS=1; 100ms; DTR=1 RTS=0; 50ms; DTR=0");
+    assert_eq!(PinSequence::parse(seq.name.clone(), &text).unwrap(), seq);
+}
+
+#[test]
+fn parse_accepts_newlines_and_rejects_garbage() {
+    let seq = PinSequence::parse("stm32", "rts=1\n20ms\nDTR=1\n").unwrap();
+    assert_eq!(
+        seq.steps,
+        [PinStep::Rts(true), PinStep::Delay(Duration::from_millis(20)), PinStep::Dtr(true)]
+    );
+    assert!(PinSequence::parse("bad", "DTR=2").is_err());
+    assert!(PinSequence::parse("bad", "fastms").is_err());
+}
+
+#[test]
+fn worker_runs_steps_in_order_with_delays() {
+    let recorder = Recorder::default();
+    let service = SerialService::with_transport(SerialConfig::default(), Box::new(recorder.clone())).unwrap();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Opened(_)));
+
+    service.run_pin_sequence(PinSequence::esp32_classic_reset()).unwrap();
+    match service.events().recv_timeout(TIMEOUT).unwrap() {
+        SerialEvent::PinSequenceDone(name) => assert_eq!(name, "ESP32 classic reset"),
+        other => panic!("expected PinSequenceDone, got {other:?}"),
+    }
+
+    let changes = recorder.changes.lock().unwrap();
+    let lines: Vec<_> = changes.iter().map(|&(_, line, state)| (line, state)).collect();
+    assert_eq!(
+        lines,
+        [("DTR", false), ("RTS", true), ("DTR", true), ("RTS", false), ("DTR", false)]
+    );
+    assert!(changes[2].0 - changes[1].0 >= Duration::from_millis(100));
+    assert!(changes[4].0 - changes[3].0 >= Duration::from_millis(50));
+}
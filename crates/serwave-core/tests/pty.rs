@@ -0,0 +1,157 @@
+#![cfg(target_os = "linux")]
+
+use serwave_core::{PtyPair, SerialConfig, SerialError, SerialEvent, SerialService};
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn next_event(service: &SerialService) -> SerialEvent {
+    service
+        .events()
+        .recv_timeout(TIMEOUT)
+        .expect("no event within timeout")
+}
+
+fn open_pair() -> (PtyPair, SerialService) {
+    let pair = PtyPair::open().expect("openpty");
+    let service = SerialService::open(pair.config()).expect("open service");
+    match next_event(&service) {
+        SerialEvent::Opened(name) => assert_eq!(name, pair.slave_path()),
+        other => panic!("expected Opened, got {other:?}"),
+    }
+    (pair, service)
+}
+
+fn read_master(pair: &mut PtyPair, len: usize) -> Vec<u8> {
+    let mut out = vec![0u8; len];
+    pair.master().read_exact(&mut out).expect("read master");
+    out
+}
+
+fn collect_rx(service: &SerialService, len: usize) -> Vec<u8> {
+    let deadline = Instant::now() + TIMEOUT;
+    let mut data = Vec::new();
+    while data.len() < len && Instant::now() < deadline {
+        if let SerialEvent::Rx { data: chunk, .. } = next_event(service) {
+            data.extend_from_slice(&chunk);
+        }
+    }
+    data
+}
+
+#[test]
+fn open_and_close() {
+    let (_pair, service) = open_pair();
+    service.close();
+    assert!(matches!(next_event(&service), SerialEvent::Closed));
+}
+
+#[test]
+fn tx_reaches_master() {
+    let (mut pair, service) = open_pair();
+    service.send(b"ping\r\n".to_vec()).unwrap();
+    assert!(matches!(next_event(&service), SerialEvent::Tx(6)));
+    assert_eq!(read_master(&mut pair, 6), b"ping\r\n");
+}
+
+#[test]
+fn rx_from_master() {
+    let (mut pair, service) = open_pair();
+    pair.write_all(b"hello\n").unwrap();
+    assert_eq!(collect_rx(&service, 6), b"hello\n");
+}
+
+#[test]
+fn rx_and_tx_keep_order() {
+    let (mut pair, service) = open_pair();
+    pair.write_all(b"first").unwrap();
+    assert_eq!(collect_rx(&service, 5), b"first");
+
+    service.send(b"reply".to_vec()).unwrap();
+    assert!(matches!(next_event(&service), SerialEvent::Tx(5)));
+    assert_eq!(read_master(&mut pair, 5), b"reply");
+
+    pair.write_all(b"second").unwrap();
+    assert_eq!(collect_rx(&service, 6), b"second");
+}
+
+#[test]
+fn rx_is_stamped_when_read_not_when_drained() {
+    let (mut pair, service) = open_pair();
+    let before = Instant::now();
+    pair.write_all(b"a").unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    pair.write_all(b"b").unwrap();
+    // Drain late, like a UI timer would
+    std::thread::sleep(Duration::from_millis(200));
+
+    let mut stamps = Vec::new();
+    while stamps.len() < 2 {
+        if let SerialEvent::Rx { data, at } = next_event(&service) {
+            stamps.extend(data.iter().map(|_| at));
+        }
+    }
+    assert!(stamps[0].mono >= before);
+    assert!(stamps[0].mono - before < Duration::from_millis(50));
+    let gap = stamps[1].since(&stamps[0]);
+    assert!(gap > Duration::from_millis(90) && gap < Duration::from_millis(200), "gap {gap:?}");
+}
+
+#[test]
+fn reconfigure_keeps_port_open() {
+    let (mut pair, service) = open_pair();
+    let cfg = SerialConfig {
+        baud_rate: 921600,
+        ..pair.config()
+    };
+    service.reconfigure(cfg).unwrap();
+    match next_event(&service) {
+        SerialEvent::Reconfigured(cfg) => assert_eq!(cfg.baud_rate, 921600),
+        other => panic!("expected Reconfigured, got {other:?}"),
+    }
+    assert_eq!(service.config().baud_rate, 921600);
+
+    service.send(b"fast".to_vec()).unwrap();
+    assert_eq!(read_master(&mut pair, 4), b"fast");
+}
+
+#[test]
+fn closed_on_hangup() {
+    let (pair, service) = open_pair();
+    pair.hang_up();
+    match next_event(&service) {
+        SerialEvent::Disconnected(e) => assert!(e.is_disconnect(), "{e}"),
+        other => panic!("expected Disconnected, got {other:?}"),
+    }
+    assert!(matches!(next_event(&service), SerialEvent::Closed));
+}
+
+#[test]
+fn open_missing_device_reports_error() {
+    let cfg = SerialConfig {
+        port_name: "/dev/serwave-does-not-exist".into(),
+        ..Default::default()
+    };
+    let service = SerialService::open(cfg).unwrap();
+    match next_event(&service) {
+        SerialEvent::Error(e) => assert!(matches!(e, SerialError::NotFound(_)), "{e}"),
+        other => panic!("expected Error, got {other:?}"),
+    }
+    assert!(matches!(next_event(&service), SerialEvent::Closed));
+}
+
+#[test]
+fn master_side_as_transport() {
+    let pair = PtyPair::open().unwrap();
+    let slave_cfg = pair.config();
+    let device = SerialService::with_transport(SerialConfig::default(), Box::new(pair.into_master_transport())).unwrap();
+    assert!(matches!(next_event(&device), SerialEvent::Opened(_)));
+    let host = SerialService::open(slave_cfg).unwrap();
+    assert!(matches!(next_event(&host), SerialEvent::Opened(_)));
+
+    host.send(b"AT\r".to_vec()).unwrap();
+    assert_eq!(collect_rx(&device, 3), b"AT\r");
+    device.send(b"OK\r".to_vec()).unwrap();
+    assert_eq!(collect_rx(&host, 3), b"OK\r");
+}
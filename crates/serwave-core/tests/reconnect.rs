@@ -0,0 +1,127 @@
+//! Reconnect behaviour, driven by mock ports that can be hung up and replaced.
+
+use serwave_core::{MockPort, ReconnectPolicy, SerialConfig, SerialError, SerialEvent, SerialService, Transport, TxPacing};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn next_event(service: &SerialService) -> SerialEvent {
+    service
+        .events()
+        .recv_timeout(TIMEOUT)
+        .expect("no event within timeout")
+}
+
+fn policy(max_attempts: Option<u32>) -> ReconnectPolicy {
+    ReconnectPolicy {
+        initial_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(40),
+        multiplier: 2,
+        max_attempts,
+    }
+}
+
+/// Opens a service whose successive connects hand out `ports` in order; once
+/// they run out the device is "unplugged".
+fn open_with_ports(ports: Vec<MockPort>, reconnect: Option<ReconnectPolicy>) -> SerialService {
+    let ports = Arc::new(Mutex::new(VecDeque::from(ports)));
+    let cfg = SerialConfig {
+        port_name: "mock".into(),
+        reconnect,
+        ..Default::default()
+    };
+    let service = SerialService::open_with(cfg, move |cfg| {
+        ports
+            .lock()
+            .unwrap()
+            .pop_front()
+            .map(|p| Box::new(p.transport()) as Box<dyn Transport>)
+            .ok_or_else(|| SerialError::NotFound(cfg.port_name.clone()))
+    })
+    .unwrap();
+    assert!(matches!(next_event(&service), SerialEvent::Opened(_)));
+    service
+}
+
+#[test]
+fn closes_after_disconnect_without_policy() {
+    let port = MockPort::new();
+    let service = open_with_ports(vec![port.clone()], None);
+    port.hang_up();
+    assert!(matches!(next_event(&service), SerialEvent::Disconnected(e) if e.is_disconnect()));
+    assert!(matches!(next_event(&service), SerialEvent::Closed));
+}
+
+#[test]
+fn reconnects_and_keeps_serving() {
+    let first = MockPort::new();
+    let second = MockPort::new();
+    let service = open_with_ports(vec![first.clone(), second.clone()], Some(policy(None)));
+    first.hang_up();
+    assert!(matches!(next_event(&service), SerialEvent::Disconnected(_)));
+    assert!(matches!(next_event(&service), SerialEvent::Reconnecting { attempt: 1, .. }));
+    assert!(matches!(next_event(&service), SerialEvent::Reconnected(name) if name == "mock"));
+
+    service.send(b"again".to_vec()).unwrap();
+    assert!(matches!(next_event(&service), SerialEvent::Tx(5)));
+    assert_eq!(second.written(), b"again");
+}
+
+#[test]
+fn keeps_pacing_set_while_reconnecting() {
+    let first = MockPort::new();
+    let second = MockPort::new();
+    let cfg_policy = ReconnectPolicy {
+        initial_delay: Duration::from_millis(200),
+        ..policy(None)
+    };
+    let service = open_with_ports(vec![first.clone(), second], Some(cfg_policy));
+    first.hang_up();
+    assert!(matches!(next_event(&service), SerialEvent::Disconnected(_)));
+    assert!(matches!(next_event(&service), SerialEvent::Reconnecting { attempt: 1, .. }));
+
+    let pacing = TxPacing {
+        inter_byte: Duration::from_millis(1),
+        inter_line: Duration::from_millis(5),
+    };
+    service.set_pacing(Some(pacing)).unwrap();
+    assert!(matches!(next_event(&service), SerialEvent::Reconnected(_)));
+    assert_eq!(service.config().pacing, Some(pacing));
+}
+
+#[test]
+fn gives_up_after_max_attempts() {
+    let port = MockPort::new();
+    let service = open_with_ports(vec![port.clone()], Some(policy(Some(3))));
+    port.hang_up();
+    assert!(matches!(next_event(&service), SerialEvent::Disconnected(_)));
+    let mut delays = Vec::new();
+    loop {
+        match next_event(&service) {
+            SerialEvent::Reconnecting { delay, .. } => delays.push(delay),
+            SerialEvent::Closed => break,
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+    assert_eq!(
+        delays,
+        [Duration::from_millis(10), Duration::from_millis(20), Duration::from_millis(40)]
+    );
+}
+
+#[test]
+fn close_aborts_reconnect() {
+    let port = MockPort::new();
+    let cfg_policy = ReconnectPolicy {
+        initial_delay: Duration::from_secs(5),
+        ..policy(None)
+    };
+    let service = open_with_ports(vec![port.clone()], Some(cfg_policy));
+    port.hang_up();
+    assert!(matches!(next_event(&service), SerialEvent::Disconnected(_)));
+    assert!(matches!(next_event(&service), SerialEvent::Reconnecting { .. }));
+    service.close();
+    assert!(matches!(next_event(&service), SerialEvent::Closed));
+}
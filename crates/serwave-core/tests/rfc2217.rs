@@ -0,0 +1,314 @@
+//! Drives the RFC 2217 client against a small in-process stand-in port server.
+
+use serwave_core::{DataBits, FlowControl, Parity, SerialConfig, SerialEvent, SerialService, StopBits};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const IAC: u8 = 255;
+const WILL: u8 = 251;
+const DO: u8 = 253;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const COM_PORT_OPTION: u8 = 44;
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Default, Debug)]
+struct RemotePort {
+    baud_rate: u32,
+    data_size: u8,
+    parity: u8,
+    stop_size: u8,
+    flow_control: u8,
+    dtr: bool,
+    rts: bool,
+    brk: bool,
+    /// How long modem-state polls go unanswered
+    modem_reply_delay: Duration,
+}
+
+impl RemotePort {
+    // Loopback plug wiring: RTS→CTS, DTR→DSR.
+    fn modem_state(&self) -> u8 {
+        (if self.rts { 0x10 } else { 0 }) | (if self.dtr { 0x20 } else { 0 })
+    }
+}
+
+fn escape(data: &[u8]) -> Vec<u8> {
+    data.iter()
+        .flat_map(|&b| if b == IAC { vec![IAC, IAC] } else { vec![b] })
+        .collect()
+}
+
+fn reply(stream: &mut TcpStream, cmd: u8, value: &[u8]) {
+    let mut out = vec![IAC, SB, COM_PORT_OPTION, cmd + 100];
+    out.extend(escape(value));
+    out.extend([IAC, SE]);
+    stream.write_all(&out).unwrap();
+}
+
+/// Minimal RFC 2217 server: acknowledges every COM-port command, answers
+/// modem-state polls and echoes data back.
+fn spawn_server() -> (String, Arc<Mutex<RemotePort>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let remote = Arc::new(Mutex::new(RemotePort::default()));
+    let state = remote.clone();
+
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.write_all(&[IAC, DO, COM_PORT_OPTION, IAC, WILL, COM_PORT_OPTION]).unwrap();
+
+        let mut bytes = Vec::new();
+        let mut buf = [0u8; 1024];
+        loop {
+            let n = match stream.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => n,
+            };
+            bytes.extend_from_slice(&buf[..n]);
+
+            let mut i = 0;
+            let mut echo = Vec::new();
+            while i < bytes.len() {
+                if bytes[i] != IAC {
+                    echo.push(bytes[i]);
+                    i += 1;
+                    continue;
+                }
+                let Some(&cmd) = bytes.get(i + 1) else { break };
+                match cmd {
+                    IAC => {
+                        echo.push(IAC);
+                        i += 2;
+                    }
+                    SB => {
+                        let Some(end) = (i + 2..bytes.len().saturating_sub(1))
+                            .find(|&j| bytes[j] == IAC && bytes[j + 1] == SE)
+                        else {
+                            break;
+                        };
+                        let sub: Vec<u8> = bytes[i + 2..end].to_vec();
+                        i = end + 2;
+                        if sub.first() != Some(&COM_PORT_OPTION) || sub.len() < 2 {
+                            continue;
+                        }
+                        let value = &sub[2..];
+                        let mut port = state.lock().unwrap();
+                        match sub[1] {
+                            1 => port.baud_rate = u32::from_be_bytes(value.try_into().unwrap()),
+                            2 => port.data_size = value[0],
+                            3 => port.parity = value[0],
+                            4 => port.stop_size = value[0],
+                            5 => match value[0] {
+                                1..=3 => port.flow_control = value[0],
+                                5 => port.brk = true,
+                                6 => port.brk = false,
+                                8 => port.dtr = true,
+                                9 => port.dtr = false,
+                                11 => port.rts = true,
+                                12 => port.rts = false,
+                                _ => {}
+                            },
+                            7 => {
+                                let (modem, delay) = (port.modem_state(), port.modem_reply_delay);
+                                drop(port);
+                                if delay.is_zero() {
+                                    reply(&mut stream, 7, &[modem]);
+                                } else {
+                                    let mut stream = stream.try_clone().unwrap();
+                                    std::thread::spawn(move || {
+                                        std::thread::sleep(delay);
+                                        reply(&mut stream, 7, &[modem]);
+                                    });
+                                }
+                                continue;
+                            }
+                            _ => {}
+                        }
+                        drop(port);
+                        reply(&mut stream, sub[1], value);
+                    }
+                    _ if i + 2 < bytes.len() => i += 3,
+                    _ => break,
+                }
+            }
+            bytes.drain(..i);
+            if !echo.is_empty() {
+                stream.write_all(&escape(&echo)).unwrap();
+            }
+        }
+    });
+
+    (addr, remote)
+}
+
+fn open(addr: &str, cfg: SerialConfig) -> SerialService {
+    let service = SerialService::open(SerialConfig {
+        port_name: format!("rfc2217://{addr}"),
+        ..cfg
+    })
+    .unwrap();
+    match service.events().recv_timeout(TIMEOUT).unwrap() {
+        SerialEvent::Opened(_) => service,
+        other => panic!("expected Opened, got {other:?}"),
+    }
+}
+
+fn wait_until(cond: impl Fn() -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !cond() {
+        assert!(Instant::now() < deadline, "condition not reached in time");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn negotiates_line_settings_on_open() {
+    let (addr, remote) = spawn_server();
+    let _service = open(
+        &addr,
+        SerialConfig {
+            baud_rate: 9600,
+            data_bits: DataBits::Seven,
+            parity: Parity::Even,
+            stop_bits: StopBits::Two,
+            flow_control: FlowControl::Hardware,
+            ..Default::default()
+        },
+    );
+
+    let port = remote.lock().unwrap();
+    assert_eq!(port.baud_rate, 9600);
+    assert_eq!(port.data_size, 7);
+    assert_eq!(port.parity, 3);
+    assert_eq!(port.stop_size, 2);
+    assert_eq!(port.flow_control, 3);
+}
+
+#[test]
+fn reconfigures_remote_port_while_open() {
+    let (addr, remote) = spawn_server();
+    let service = open(&addr, SerialConfig::default());
+
+    service
+        .reconfigure(SerialConfig {
+            baud_rate: 921600,
+            parity: Parity::Odd,
+            ..Default::default()
+        })
+        .unwrap();
+    match service.events().recv_timeout(TIMEOUT).unwrap() {
+        SerialEvent::Reconfigured(cfg) => {
+            assert_eq!(cfg.baud_rate, 921600);
+            assert_eq!(cfg.port_name, format!("rfc2217://{addr}"));
+        }
+        other => panic!("expected Reconfigured, got {other:?}"),
+    }
+    assert_eq!(service.config().baud_rate, 921600);
+
+    let port = remote.lock().unwrap();
+    assert_eq!(port.baud_rate, 921600);
+    assert_eq!(port.parity, 2);
+}
+
+#[test]
+fn forwards_dtr_rts_and_reads_modem_lines() {
+    let (addr, remote) = spawn_server();
+    let service = open(&addr, SerialConfig::default());
+
+    service.set_dtr(true).unwrap();
+    service.set_rts(false).unwrap();
+    wait_until(|| remote.lock().unwrap().dtr);
+
+    service.request_pin_states().unwrap();
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        match service.events().recv_deadline(deadline).unwrap() {
+            SerialEvent::PinStates(states) => {
+                assert!(states.dsr);
+                assert!(!states.cts);
+                break;
+            }
+            SerialEvent::Error(e) => panic!("{e}"),
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn slow_modem_state_answers_do_not_hold_up_sends() {
+    let (addr, remote) = spawn_server();
+    remote.lock().unwrap().modem_reply_delay = Duration::from_millis(150);
+    let service = open(&addr, SerialConfig::default());
+
+    // Line sampling keeps polling in the background; each echo should come
+    // straight back rather than after a poll's answer
+    for _ in 0..10 {
+        let start = Instant::now();
+        service.send(b"x".to_vec()).unwrap();
+        loop {
+            match service.events().recv_timeout(TIMEOUT).unwrap() {
+                SerialEvent::Rx { .. } => break,
+                SerialEvent::Error(e) => panic!("{e}"),
+                _ => {}
+            }
+        }
+        assert!(start.elapsed() < Duration::from_millis(100), "echo took {:?}", start.elapsed());
+    }
+}
+
+#[test]
+fn send_break_holds_then_releases_line() {
+    let (addr, remote) = spawn_server();
+    let service = open(&addr, SerialConfig::default());
+
+    service.send_break(Duration::from_millis(100)).unwrap();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Break(true)));
+    assert!(remote.lock().unwrap().brk);
+
+    let started = Instant::now();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Break(false)));
+    assert!(started.elapsed() >= Duration::from_millis(50));
+    assert!(!remote.lock().unwrap().brk);
+}
+
+#[test]
+fn data_with_iac_bytes_round_trips() {
+    let (addr, _remote) = spawn_server();
+    let service = open(&addr, SerialConfig::default());
+
+    let payload = vec![0x01, 0xFF, 0xFF, 0x7E, 0xFF];
+    service.send(payload.clone()).unwrap();
+
+    let deadline = Instant::now() + TIMEOUT;
+    let mut rx = Vec::new();
+    while rx.len() < payload.len() {
+        if let SerialEvent::Rx { data, .. } = service.events().recv_deadline(deadline).unwrap() {
+            rx.extend(data);
+        }
+    }
+    assert_eq!(rx, payload);
+}
+
+#[test]
+fn refuses_plain_tcp_peer() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || {
+        let (_stream, _) = listener.accept().unwrap();
+        std::thread::sleep(TIMEOUT);
+    });
+
+    let service = SerialService::open(SerialConfig {
+        port_name: format!("rfc2217://{addr}"),
+        ..Default::default()
+    })
+    .unwrap();
+    match service.events().recv_timeout(TIMEOUT).unwrap() {
+        SerialEvent::Error(e) => assert!(e.to_string().contains("RFC 2217"), "{e}"),
+        other => panic!("expected Error, got {other:?}"),
+    }
+}
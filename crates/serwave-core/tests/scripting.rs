@@ -0,0 +1,107 @@
+#![cfg(feature = "scripting")]
+
+use serwave_core::{MockPort, ScriptError, ScriptHost, ScriptOutput, SerialConfig, SerialEvent, SerialService};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn open() -> (MockPort, SerialService) {
+    let port = MockPort::new();
+    let service = port.open(SerialConfig::default()).unwrap();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Opened(_)));
+    (port, service)
+}
+
+/// Everything the script reports, up to its result.
+fn finish(host: &ScriptHost) -> (Vec<ScriptOutput>, Result<(), ScriptError>) {
+    let mut outputs = Vec::new();
+    loop {
+        match host.output().recv_timeout(TIMEOUT).expect("script did not finish") {
+            ScriptOutput::Finished(result) => return (outputs, result),
+            output => outputs.push(output),
+        }
+    }
+}
+
+fn texts(outputs: &[ScriptOutput]) -> Vec<String> {
+    outputs
+        .iter()
+        .map(|o| match o {
+            ScriptOutput::Print(s) => format!("print {s}"),
+            ScriptOutput::Log(s) => format!("log {s}"),
+            ScriptOutput::Finished(_) => unreachable!(),
+        })
+        .collect()
+}
+
+#[test]
+fn handlers_keep_state_between_events() {
+    let (port, service) = open();
+    let host = ScriptHost::start(
+        r#"
+        let seen = 0;
+        fn on_rx(data) {
+            seen += data.len();
+            if seen >= 9 {
+                log(`crc ${crc16_modbus(data)}`);
+                exit();
+            }
+        }
+        send_hex("01 03");
+        "#,
+        service.handle(),
+    )
+    .unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    port.inject_rx(b"123456789");
+
+    let (outputs, result) = finish(&host);
+    result.unwrap();
+    assert_eq!(texts(&outputs), ["log crc 19255"]);
+    assert_eq!(port.take_written(), [0x01, 0x03]);
+}
+
+#[test]
+fn timers_fire_until_exit() {
+    let (_port, service) = open();
+    let path = std::env::temp_dir().join(format!("serwave-script-{}.txt", std::process::id()));
+    let source = format!(
+        r#"
+        let n = 0;
+        fn tick() {{
+            n += 1;
+            print(`tick ${{n}}`);
+            if n == 3 {{
+                write_file({path:?}, `${{n}}`);
+                exit();
+            }}
+        }}
+        print(crc32("123456789".to_blob()));
+        every(20, "tick");
+        "#
+    );
+    let host = ScriptHost::start(&source, service.handle()).unwrap();
+
+    let (outputs, result) = finish(&host);
+    result.unwrap();
+    assert_eq!(texts(&outputs), ["print 3421780262", "print tick 1", "print tick 2", "print tick 3"]);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "3");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn errors_and_stopping() {
+    let (_port, service) = open();
+    assert!(matches!(ScriptHost::start("let = ;", service.handle()), Err(ScriptError::Compile(_))));
+
+    let host = ScriptHost::start(r#"send_hex("zz");"#, service.handle()).unwrap();
+    match finish(&host).1 {
+        Err(ScriptError::Runtime(message)) => assert!(message.contains("bad hex"), "{message}"),
+        other => panic!("expected a runtime error, got {other:?}"),
+    }
+
+    let host = ScriptHost::start("loop { sleep(10); }", service.handle()).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    host.stop();
+    assert!(matches!(finish(&host).1, Err(ScriptError::Stopped)));
+}
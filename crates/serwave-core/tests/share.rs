@@ -0,0 +1,183 @@
+//! Shares a mock port over loopback TCP and checks what clients see and send.
+
+use serwave_core::{
+    DataBits, MockPort, Parity, Permission, SerialConfig, SerialEvent, SerialService, ShareConfig, ShareEvent, ShareProtocol,
+    ShareServer, StopBits,
+};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn share(permission: Permission) -> (MockPort, SerialService, ShareServer) {
+    let port = MockPort::new();
+    let service = port.open(SerialConfig::default()).unwrap();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Opened(_)));
+    let server = ShareServer::start(
+        &service,
+        ShareConfig {
+            bind_addr: "127.0.0.1:0".to_string(),
+            default_permission: permission,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    (port, service, server)
+}
+
+/// Connects a client and waits for the server to register it.
+fn connect(server: &ShareServer) -> (u64, TcpStream) {
+    let stream = TcpStream::connect(server.local_addr()).unwrap();
+    stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+    match next_event(server) {
+        ShareEvent::Connected(info) => (info.id, stream),
+        other => panic!("unexpected event {other:?}"),
+    }
+}
+
+fn next_event(server: &ShareServer) -> ShareEvent {
+    server.events().recv_timeout(TIMEOUT).expect("no event within timeout")
+}
+
+fn read_exact(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+    let mut buf = vec![0; len];
+    stream.read_exact(&mut buf).unwrap();
+    buf
+}
+
+fn wait_until(cond: impl Fn() -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !cond() {
+        assert!(Instant::now() < deadline, "condition not met within timeout");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn fans_rx_out_to_every_client() {
+    let (port, _service, server) = share(Permission::ReadOnly);
+    let mut clients: Vec<TcpStream> = (0..3).map(|_| connect(&server).1).collect();
+
+    port.inject_rx(b"hello");
+    for client in &mut clients {
+        assert_eq!(read_exact(client, 5), b"hello");
+    }
+}
+
+#[test]
+fn merges_writes_from_clients_into_tx() {
+    let (port, _service, server) = share(Permission::ReadWrite);
+    let (a, mut stream_a) = connect(&server);
+    let (b, mut stream_b) = connect(&server);
+
+    stream_a.write_all(b"one;").unwrap();
+    match next_event(&server) {
+        ShareEvent::Tx { client, data } => assert_eq!((client, data.as_slice()), (a, &b"one;"[..])),
+        other => panic!("unexpected event {other:?}"),
+    }
+    stream_b.write_all(b"two;").unwrap();
+    match next_event(&server) {
+        ShareEvent::Tx { client, data } => assert_eq!((client, data.as_slice()), (b, &b"two;"[..])),
+        other => panic!("unexpected event {other:?}"),
+    }
+    wait_until(|| port.written() == b"one;two;");
+}
+
+#[test]
+fn drops_writes_from_read_only_clients() {
+    let (port, _service, server) = share(Permission::ReadOnly);
+    let (reader, mut reader_stream) = connect(&server);
+    let (writer, mut writer_stream) = connect(&server);
+    server.set_permission(writer, Permission::ReadWrite);
+
+    reader_stream.write_all(b"ignored").unwrap();
+    match next_event(&server) {
+        ShareEvent::Denied { client, len } => assert_eq!((client, len), (reader, 7)),
+        other => panic!("unexpected event {other:?}"),
+    }
+    writer_stream.write_all(b"kept").unwrap();
+    assert!(matches!(next_event(&server), ShareEvent::Tx { client, .. } if client == writer));
+    wait_until(|| port.written() == b"kept");
+}
+
+#[test]
+fn drops_a_stalled_client_without_holding_up_the_rest() {
+    let (port, _service, server) = share(Permission::ReadOnly);
+    let (stalled, _stalled_stream) = connect(&server);
+    let (_, mut healthy) = connect(&server);
+
+    let reader = std::thread::spawn(move || {
+        let mut buf = [0u8; 65536];
+        let mut total = 0;
+        loop {
+            let n = healthy.read(&mut buf).unwrap();
+            assert!(n > 0, "healthy client was disconnected");
+            total += n;
+            if buf[..n].ends_with(b"end") {
+                return total;
+            }
+        }
+    });
+
+    // Keep RX flowing until the client that never reads overflows its queue
+    let chunk = vec![0x55u8; 65536];
+    let mut injected = 0;
+    let dropped = loop {
+        assert!(injected < 2000 * chunk.len(), "stalled client was never dropped");
+        port.inject_rx(&chunk);
+        injected += chunk.len();
+        std::thread::sleep(Duration::from_millis(1));
+        match server.events().try_recv() {
+            Ok(ShareEvent::Disconnected(id)) => break id,
+            Ok(other) => panic!("unexpected event {other:?}"),
+            Err(_) => {}
+        }
+    };
+    assert_eq!(dropped, stalled);
+    assert_eq!(server.clients().len(), 1);
+
+    // The healthy client still gets everything
+    port.inject_rx(b"end");
+    assert_eq!(reader.join().unwrap(), injected + 3);
+}
+
+#[test]
+fn applies_a_burst_of_rfc2217_line_settings_at_once() {
+    let port = MockPort::new();
+    let service = port.open(SerialConfig::default()).unwrap();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Opened(_)));
+    let server = ShareServer::start(
+        &service,
+        ShareConfig {
+            bind_addr: "127.0.0.1:0".to_string(),
+            protocol: ShareProtocol::Rfc2217,
+            default_permission: Permission::ReadWrite,
+        },
+    )
+    .unwrap();
+    let (_, mut client) = connect(&server);
+
+    // IAC SB COM-PORT-OPTION <cmd> <value> IAC SE, for baud, data bits, parity and stop bits
+    let mut burst = Vec::new();
+    for (cmd, value) in [(1, &9600u32.to_be_bytes()[..]), (2, &[7]), (3, &[3]), (4, &[2])] {
+        burst.extend([255, 250, 44, cmd]);
+        burst.extend(value);
+        burst.extend([255, 240]);
+    }
+    client.write_all(&burst).unwrap();
+
+    wait_until(|| port.line_settings().is_some());
+    let cfg = port.line_settings().unwrap();
+    assert_eq!(
+        (cfg.baud_rate, cfg.data_bits, cfg.parity, cfg.stop_bits),
+        (9600, DataBits::Seven, Parity::Even, StopBits::Two)
+    );
+    let mut reconfigured = 0;
+    while let Ok(event) = service.events().recv_timeout(Duration::from_millis(200)) {
+        if matches!(event, SerialEvent::Reconfigured(_)) {
+            reconfigured += 1;
+        }
+    }
+    assert_eq!(reconfigured, 1);
+}
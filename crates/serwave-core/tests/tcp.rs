@@ -0,0 +1,108 @@
+//! Opens the raw TCP transports by name against loopback peers.
+
+use serwave_core::{Endpoint, SerialConfig, SerialEvent, SerialService};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn open(port_name: String) -> SerialService {
+    let service = SerialService::open(SerialConfig { port_name, ..Default::default() }).unwrap();
+    match service.events().recv_timeout(TIMEOUT).unwrap() {
+        SerialEvent::Opened(_) => service,
+        other => panic!("expected Opened, got {other:?}"),
+    }
+}
+
+/// Collects RX until `len` bytes arrived.
+fn receive(service: &SerialService, len: usize) -> Vec<u8> {
+    let deadline = Instant::now() + TIMEOUT;
+    let mut data = Vec::new();
+    while data.len() < len {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match service.events().recv_timeout(left).expect("no RX within timeout") {
+            SerialEvent::Rx { data: chunk, .. } => data.extend(chunk),
+            SerialEvent::Tx(_) => {}
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+    data
+}
+
+fn read_exact(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+    let mut buf = vec![0; len];
+    stream.read_exact(&mut buf).unwrap();
+    buf
+}
+
+/// An address nothing listens on right now.
+fn free_addr() -> String {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string()
+}
+
+#[test]
+fn parses_tcp_endpoints() {
+    assert_eq!(Endpoint::parse("tcp://10.0.0.2:23"), Endpoint::TcpClient("10.0.0.2:23".into()));
+    assert_eq!(Endpoint::parse("tcp-listen://0.0.0.0:4000"), Endpoint::TcpServer("0.0.0.0:4000".into()));
+}
+
+#[test]
+fn client_round_trips_data() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let service = open(format!("tcp://{}", listener.local_addr().unwrap()));
+    let (mut peer, _) = listener.accept().unwrap();
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+    service.send(b"ping".to_vec()).unwrap();
+    assert_eq!(read_exact(&mut peer, 4), b"ping");
+    peer.write_all(b"pong").unwrap();
+    assert_eq!(receive(&service, 4), b"pong");
+}
+
+#[test]
+fn client_reports_peer_hang_up_as_disconnect() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let service = open(format!("tcp://{}", listener.local_addr().unwrap()));
+    let (peer, _) = listener.accept().unwrap();
+    drop(peer);
+
+    match service.events().recv_timeout(TIMEOUT).unwrap() {
+        SerialEvent::Disconnected(e) => assert!(e.is_disconnect()),
+        other => panic!("expected Disconnected, got {other:?}"),
+    }
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Closed));
+}
+
+#[test]
+fn server_round_trips_data() {
+    let addr = free_addr();
+    let service = open(format!("tcp-listen://{addr}"));
+    let mut peer = TcpStream::connect(&addr).unwrap();
+    peer.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+    peer.write_all(b"hello").unwrap();
+    assert_eq!(receive(&service, 5), b"hello");
+    service.send(b"world".to_vec()).unwrap();
+    assert_eq!(read_exact(&mut peer, 5), b"world");
+}
+
+#[test]
+fn server_stays_open_for_the_next_client_after_a_hang_up() {
+    let addr = free_addr();
+    let service = open(format!("tcp-listen://{addr}"));
+    let mut first = TcpStream::connect(&addr).unwrap();
+    first.write_all(b"1").unwrap();
+    assert_eq!(receive(&service, 1), b"1");
+    drop(first);
+    // A client arriving before the server notices the hang-up is turned away
+    std::thread::sleep(Duration::from_millis(200));
+
+    // The listener outlives its clients, so a hang-up is not a disconnect
+    let mut second = TcpStream::connect(&addr).unwrap();
+    second.set_read_timeout(Some(TIMEOUT)).unwrap();
+    second.write_all(b"2").unwrap();
+    assert_eq!(receive(&service, 1), b"2");
+    service.send(b"ok".to_vec()).unwrap();
+    assert_eq!(read_exact(&mut second, 2), b"ok");
+}
@@ -0,0 +1,109 @@
+use serwave_core::{MockPort, ResponseMatcher, SerialConfig, SerialEvent, SerialService, Transaction, TransactionError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+fn open() -> (MockPort, SerialService) {
+    let port = MockPort::new();
+    let service = port.open(SerialConfig::default()).unwrap();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Opened(_)));
+    (port, service)
+}
+
+/// Plays a device that answers `request` with `chunks`, `delay` apart.
+fn respond(port: &MockPort, request: &'static [u8], delay: Duration, chunks: &'static [&'static [u8]]) -> JoinHandle<()> {
+    let port = port.clone();
+    std::thread::spawn(move || {
+        let deadline = Instant::now() + TIMEOUT;
+        while port.written() != request {
+            assert!(Instant::now() < deadline, "request never written");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        for chunk in chunks {
+            std::thread::sleep(delay);
+            port.inject_rx(chunk);
+        }
+    })
+}
+
+#[test]
+fn regex_separates_unsolicited_lines() {
+    let (port, service) = open();
+    let device = respond(&port, b"AT+CSQ\r\n", Duration::ZERO, &[b"+URC: ring\r\n+CSQ: 17", b",99\r\nOK\r\n"]);
+
+    let transaction = Transaction::new("AT+CSQ\r\n", ResponseMatcher::regex(r"\+CSQ: \d+,\d+\r\n").unwrap());
+    let result = service.transact(&transaction).unwrap();
+    assert_eq!(result.response, b"+CSQ: 17,99\r\n");
+    assert_eq!(result.unsolicited, b"+URC: ring\r\nOK\r\n");
+    device.join().unwrap();
+}
+
+#[test]
+fn latency_runs_from_write_to_arrival() {
+    let (port, service) = open();
+    let device = respond(&port, b"?", Duration::from_millis(50), &[b"ab", b"cd"]);
+
+    let result = service.transact(&Transaction::new("?", ResponseMatcher::ByteCount(3))).unwrap();
+    assert_eq!(result.response, b"abc");
+    assert_eq!(result.unsolicited, b"d");
+    // Completed by the second chunk
+    assert!(result.latency >= Duration::from_millis(95), "{:?}", result.latency);
+    device.join().unwrap();
+}
+
+#[test]
+fn latency_is_measured_for_an_immediate_response() {
+    let (port, service) = open();
+    let device = respond(&port, b"AT\r", Duration::ZERO, &[b"OK\r\n"]);
+
+    let result = service.transact(&Transaction::new("AT\r", ResponseMatcher::terminator("\r\n"))).unwrap();
+    assert_eq!(result.response, b"OK\r\n");
+    assert!(result.latency > Duration::ZERO && result.latency < TIMEOUT, "{:?}", result.latency);
+    device.join().unwrap();
+}
+
+#[test]
+fn latency_is_measured_when_the_response_beats_the_write_reply() {
+    // The loopback delivers the echo while the write is still going on
+    let port = MockPort::loopback();
+    let service = port.open(SerialConfig::default()).unwrap();
+    assert!(matches!(service.events().recv_timeout(TIMEOUT).unwrap(), SerialEvent::Opened(_)));
+
+    for _ in 0..20 {
+        let result = service.transact(&Transaction::new("echo\n", ResponseMatcher::terminator("\n"))).unwrap();
+        assert_eq!(result.response, b"echo\n");
+        assert!(result.latency > Duration::ZERO, "{:?}", result.latency);
+    }
+}
+
+#[test]
+fn terminator_and_predicate() {
+    let (port, service) = open();
+    let device = respond(&port, b"ver\n", Duration::ZERO, &[b"v1.2\r\n> "]);
+    let result = service.transact(&Transaction::new("ver\n", ResponseMatcher::terminator("\r\n"))).unwrap();
+    assert_eq!(result.response, b"v1.2\r\n");
+    assert_eq!(result.unsolicited, b"> ");
+    device.join().unwrap();
+
+    port.take_written();
+    let device = respond(&port, b"sum\n", Duration::ZERO, &[&[2, 1, 2]]);
+    let complete = ResponseMatcher::predicate(|data| data.first().is_some_and(|&len| data.len() > len as usize));
+    let result = service.transact(&Transaction::new("sum\n", complete)).unwrap();
+    assert_eq!(result.response, [2, 1, 2]);
+    device.join().unwrap();
+}
+
+#[test]
+fn timeout_keeps_partial_response() {
+    let (port, service) = open();
+    let device = respond(&port, b"AT\r\n", Duration::ZERO, &[b"O"]);
+
+    let mut transaction = Transaction::new("AT\r\n", ResponseMatcher::terminator("OK\r\n"));
+    transaction.timeout = Duration::from_millis(200);
+    match service.transact(&transaction) {
+        Err(TransactionError::Timeout { received, .. }) => assert_eq!(received, b"O"),
+        other => panic!("expected timeout, got {other:?}"),
+    }
+    device.join().unwrap();
+}